@@ -75,7 +75,7 @@ pub trait Domain {
     type OutgoingViewingKey;
     type ValueCommitment;
     type ExtractedCommitment;
-    type ExtractedCommitmentBytes: Eq + TryFrom<Self::ExtractedCommitment>;
+    type ExtractedCommitmentBytes: Eq + AsRef<[u8]> + TryFrom<Self::ExtractedCommitment>;
     type Memo;
 
     fn derive_esk(note: &Self::Note) -> Option<Self::EphemeralSecretKey>;
@@ -384,8 +384,11 @@ fn check_note_validity<D: Domain>(
     epk: &D::EphemeralPublicKey,
     cmstar_bytes: &D::ExtractedCommitmentBytes,
 ) -> NoteValidity {
-    if D::ExtractedCommitmentBytes::try_from(D::cmstar(&note))
-        .map_or(false, |cs| &cs == cmstar_bytes)
+    // Validate the commitment in constant time, since this runs once per candidate
+    // output while scanning attacker-controlled block data.
+    if D::ExtractedCommitmentBytes::try_from(D::cmstar(note))
+        .map(|cs| cs.as_ref().ct_eq(cmstar_bytes.as_ref()).into())
+        .unwrap_or(false)
     {
         let epk_bytes = D::epk_bytes(epk);
         D::check_epk_bytes(&note, |derived_esk| {
@@ -18,10 +18,16 @@ use crate::{
     address::RecipientAddress,
     data_api::wallet::ANCHOR_OFFSET,
     decrypt::DecryptedOutput,
+    keys::UnifiedFullViewingKey,
     proto::compact_formats::CompactBlock,
-    wallet::{AccountId, SpendableNote, WalletTx},
+    wallet::{AccountId, NoteSelectionStrategy, SpendableNote, WalletTx},
 };
 
+#[cfg(feature = "transparent-inputs")]
+use crate::wallet::WalletTransparentOutput;
+#[cfg(feature = "transparent-inputs")]
+use zcash_primitives::legacy::TransparentAddress;
+
 pub mod chain;
 pub mod error;
 pub mod wallet;
@@ -32,6 +38,14 @@ pub mod wallet;
 /// interface atop which higher-level wallet operations are
 /// implemented. It serves to allow wallet functions to be
 /// abstracted away from any particular data storage substrate.
+///
+/// Each method call is independent: nothing in this trait guarantees that a caller
+/// composing several calls (for example, reading the chain tip and then selecting notes
+/// against it) observes a single consistent view of the underlying store if a write is
+/// committed, by this connection or another, in between those calls. Implementations for
+/// which this matters may offer an additional, backend-specific API for grouping a
+/// sequence of reads into one snapshot; see `WalletDb::with_read_snapshot` in
+/// `zcash_client_sqlite` for an example.
 pub trait WalletRead {
     /// The type of errors produced by a wallet backend.
     type Error;
@@ -40,7 +54,7 @@ pub trait WalletRead {
     ///
     /// For example, this might be a database identifier type
     /// or a UUID.
-    type NoteRef: Copy + Debug;
+    type NoteRef: Copy + Debug + PartialEq;
 
     /// Backend-specific transaction identifier.
     ///
@@ -54,6 +68,28 @@ pub trait WalletRead {
     /// This will return `Ok(None)` if no block data is present in the database.
     fn block_height_extrema(&self) -> Result<Option<(BlockHeight, BlockHeight)>, Self::Error>;
 
+    /// Returns the earliest height the wallet needs to have scanned from, i.e. the
+    /// minimum birthday height across all accounts, or `Ok(None)` if the wallet has no
+    /// accounts.
+    ///
+    /// Unlike [`WalletRead::block_height_extrema`], which describes what has already
+    /// been scanned, this describes where scanning should have started, letting a
+    /// caller combine it with the current chain tip to compute genuine sync progress
+    /// (e.g. "scanned X of Y blocks") rather than assuming every wallet needs to scan
+    /// from the network's Sapling activation height.
+    fn get_wallet_birthday(&self) -> Result<Option<BlockHeight>, Self::Error>;
+
+    /// Returns the maximum block height for which the wallet's note commitment tree
+    /// and witness state has actually been recorded, or `Ok(None)` if none has.
+    ///
+    /// Under normal operation this coincides with the upper bound returned by
+    /// [`WalletRead::block_height_extrema`], since scanning always records a block's
+    /// tree state at the same time as the block itself; the two are named separately
+    /// so that a caller resuming a sync after a rewind or a partial import resumes
+    /// from the height that is actually safe to build new witnesses on top of, rather
+    /// than the height for which block metadata merely happens to be present.
+    fn get_max_scanned_height(&self) -> Result<Option<BlockHeight>, Self::Error>;
+
     /// Returns the default target height (for the block in which a new
     /// transaction would be mined) and anchor height (to use for a new
     /// transaction), given the range of block heights that the backend
@@ -62,15 +98,35 @@ pub trait WalletRead {
     /// This will return `Ok(None)` if no block data is present in the database.
     fn get_target_and_anchor_heights(
         &self,
+    ) -> Result<Option<(BlockHeight, BlockHeight)>, Self::Error> {
+        self.get_target_and_anchor_heights_with_min_confirmations(ANCHOR_OFFSET)
+    }
+
+    /// Returns the default target height (for the block in which a new transaction
+    /// would be mined) and an anchor height that is confirmed to at least
+    /// `min_confirmations` blocks, given the range of block heights that the backend
+    /// knows about.
+    ///
+    /// This allows a caller to request a shallower or deeper anchor than the
+    /// [`ANCHOR_OFFSET`] used by [`get_target_and_anchor_heights`] — for example, to
+    /// require fewer confirmations for a low-value payment than for a high-value one —
+    /// within the same wallet session.
+    ///
+    /// This will return `Ok(None)` if no block data is present in the database.
+    ///
+    /// [`get_target_and_anchor_heights`]: WalletRead::get_target_and_anchor_heights
+    fn get_target_and_anchor_heights_with_min_confirmations(
+        &self,
+        min_confirmations: u32,
     ) -> Result<Option<(BlockHeight, BlockHeight)>, Self::Error> {
         self.block_height_extrema().map(|heights| {
             heights.map(|(min_height, max_height)| {
                 let target_height = max_height + 1;
 
-                // Select an anchor ANCHOR_OFFSET back from the target block,
+                // Select an anchor min_confirmations back from the target block,
                 // unless that would be before the earliest block we have.
                 let anchor_height = BlockHeight::from(cmp::max(
-                    u32::from(target_height).saturating_sub(ANCHOR_OFFSET),
+                    u32::from(target_height).saturating_sub(min_confirmations),
                     u32::from(min_height),
                 ));
 
@@ -101,10 +157,54 @@ pub trait WalletRead {
             .map(|oo| oo.flatten())
     }
 
+    /// Returns the time of the block at the maximum height known in stored data, for
+    /// display purposes (e.g. "synced as of 3 minutes ago").
+    ///
+    /// This will return `Ok(None)` if no block data is present in the database.
+    fn get_tip_block_time(&self) -> Result<Option<u32>, Self::Error>;
+
+    /// Returns the wall-clock time of `height`, for display purposes (e.g. "this note
+    /// was received around March 3").
+    ///
+    /// If the wallet has a stored block at exactly this height, its recorded time is
+    /// returned directly. Otherwise, the time is linearly interpolated between the
+    /// nearest stored blocks below and above `height`. Returns `Ok(None)` only if no
+    /// such bracketing blocks are available (for example, `height` is beyond every
+    /// block the wallet has scanned).
+    fn estimate_block_time(&self, height: BlockHeight) -> Result<Option<u32>, Self::Error>;
+
     /// Returns the block height in which the specified transaction was mined,
     /// or `Ok(None)` if the transaction is not mined in the main chain.
     fn get_tx_height(&self, txid: TxId) -> Result<Option<BlockHeight>, Self::Error>;
 
+    /// Returns the full transaction for the given txid, deserialized from the raw
+    /// bytes stored by the wallet.
+    ///
+    /// Returns `Ok(None)` if the txid is not known to the wallet, or if the wallet
+    /// has only recorded metadata (no raw transaction bytes) for it.
+    fn get_transaction(&self, txid: TxId) -> Result<Option<Transaction>, Self::Error>;
+
+    /// Returns the client-supplied [`SentTransaction::proposal_id`] recorded for the
+    /// given txid, if any.
+    ///
+    /// Returns `Ok(None)` if the txid is not known to the wallet, or was not recorded
+    /// with a proposal id.
+    fn get_sent_tx_proposal_id(&self, txid: TxId) -> Result<Option<String>, Self::Error>;
+
+    /// Returns a page of the wallet's transaction history, most recent first.
+    ///
+    /// If `tip_height` is supplied, each returned [`WalletTransaction::confirmations`]
+    /// is computed against it, so that a UI paging through history does not need to
+    /// recompute confirmation counts per row itself; unmined transactions are reported
+    /// with `Some(0)` confirmations. If `tip_height` is `None`, `confirmations` is
+    /// `None` for every row.
+    fn get_transactions(
+        &self,
+        limit: usize,
+        offset: usize,
+        tip_height: Option<BlockHeight>,
+    ) -> Result<Vec<WalletTransaction>, Self::Error>;
+
     /// Returns the payment address for the specified account, if the account
     /// identifier specified refers to a valid account for this wallet.
     ///
@@ -112,11 +212,61 @@ pub trait WalletRead {
     /// to a known account.
     fn get_address(&self, account: AccountId) -> Result<Option<PaymentAddress>, Self::Error>;
 
+    /// Returns the change address for the specified account, if the account identifier
+    /// specified refers to a valid account for this wallet.
+    ///
+    /// This is the default address of the account's internal (change) extended full
+    /// viewing key, derived per [ZIP 32] by [`crate::keys::internal_extfvk`], rather
+    /// than the address returned by [`WalletRead::get_address`]; composition code that
+    /// needs "the address this account's change should be sent to" can depend on this
+    /// accessor without needing to derive the internal key itself.
+    ///
+    /// This will return `Ok(None)` if the account identifier does not correspond
+    /// to a known account.
+    ///
+    /// [ZIP 32]: https://zips.z.cash/zip-0032
+    fn get_change_address(
+        &self,
+        account: AccountId,
+    ) -> Result<Option<PaymentAddress>, Self::Error> {
+        Ok(self
+            .get_extended_full_viewing_keys()?
+            .get(&account)
+            .map(|extfvk| {
+                crate::keys::internal_extfvk(extfvk)
+                    .default_address()
+                    .unwrap()
+                    .1
+            }))
+    }
+
+    /// Returns the most recently generated diversified payment address for the
+    /// specified account, or its default address (as returned by
+    /// [`WalletRead::get_address`]) if [`WalletWrite::get_next_available_address`] has
+    /// not yet been called for this account.
+    ///
+    /// This will return `Ok(None)` if the account identifier does not correspond
+    /// to a known account.
+    fn get_current_address(&self, account: AccountId)
+        -> Result<Option<PaymentAddress>, Self::Error>;
+
     /// Returns all extended full viewing keys known about by this wallet.
     fn get_extended_full_viewing_keys(
         &self,
     ) -> Result<HashMap<AccountId, ExtendedFullViewingKey>, Self::Error>;
 
+    /// Returns all unified full viewing keys known about by this wallet.
+    ///
+    /// Unlike [`WalletRead::get_extended_full_viewing_keys`], the returned keys are
+    /// suitable for handing to a user as a single backup/export string, since a
+    /// [`UnifiedFullViewingKey`] additionally carries the transparent (and, in future,
+    /// Orchard) components of an account's full viewing key alongside its Sapling one.
+    /// Accounts that predate unified full viewing key support and have no such key on
+    /// record are omitted from the result rather than erroring.
+    fn get_unified_full_viewing_keys(
+        &self,
+    ) -> Result<HashMap<AccountId, UnifiedFullViewingKey>, Self::Error>;
+
     /// Checks whether the specified extended full viewing key is
     /// associated with the account.
     fn is_valid_account_extfvk(
@@ -125,6 +275,24 @@ pub trait WalletRead {
         extfvk: &ExtendedFullViewingKey,
     ) -> Result<bool, Self::Error>;
 
+    /// Checks whether `addr` is a diversified address of one of this wallet's accounts,
+    /// returning the owning account if so.
+    ///
+    /// This walks every known account's extended full viewing key, deriving the payment
+    /// address for `addr`'s diversifier from each account's incoming viewing key and
+    /// comparing it against `addr`. It is intended for callers (such as `is_change`
+    /// computations) that need to recognize a self-payment without having to perform
+    /// this derivation by hand.
+    fn is_own_address(&self, addr: &PaymentAddress) -> Result<Option<AccountId>, Self::Error> {
+        for (account, extfvk) in self.get_extended_full_viewing_keys()? {
+            if extfvk.fvk.vk.ivk().to_payment_address(*addr.diversifier()) == Some(addr.clone()) {
+                return Ok(Some(account));
+            }
+        }
+
+        Ok(None)
+    }
+
     /// Returns the wallet balance for an account as of the specified block
     /// height.
     ///
@@ -136,18 +304,155 @@ pub trait WalletRead {
         anchor_height: BlockHeight,
     ) -> Result<Amount, Self::Error>;
 
+    /// Returns the wallet balance for every account known to the wallet as of the
+    /// specified block height, computed in a single grouped query rather than one
+    /// [`WalletRead::get_balance_at`] call per account.
+    ///
+    /// Accounts with no spendable notes at the anchor height are included in the
+    /// result with a balance of [`Amount::zero`], so the returned map always has one
+    /// entry per account known to the wallet.
+    fn get_balances_at(
+        &self,
+        anchor_height: BlockHeight,
+    ) -> Result<HashMap<AccountId, Amount>, Self::Error>;
+
+    /// Returns `account`'s spendable balance at each of the given confirmation
+    /// thresholds, as of the wallet's current chain tip.
+    ///
+    /// This generalizes [`WalletRead::get_balance_at`], which only reports the balance
+    /// at a single anchor, to the common case of a UI wanting to show a breakdown such
+    /// as "available now" alongside "available in N blocks" for several values of `N`
+    /// at once. Each entry of `buckets` is interpreted exactly as the
+    /// `min_confirmations` parameter of
+    /// [`WalletRead::get_target_and_anchor_heights_with_min_confirmations`] is: the
+    /// anchor height for that bucket is `buckets` blocks back from one past the
+    /// wallet's highest scanned block, clamped to the earliest block the wallet knows
+    /// about. If the wallet has no block data at all, every bucket is reported as
+    /// [`Amount::zero`].
+    ///
+    /// The result has one entry per element of `buckets`, in the same order.
+    fn get_balance_by_confirmations(
+        &self,
+        account: AccountId,
+        buckets: &[u32],
+    ) -> Result<Vec<(u32, Amount)>, Self::Error> {
+        let extrema = self.block_height_extrema()?;
+        buckets
+            .iter()
+            .map(|&min_confirmations| {
+                let balance = extrema
+                    .map(|(min_height, max_height)| {
+                        let target_height = max_height + 1;
+                        let anchor_height = BlockHeight::from(cmp::max(
+                            u32::from(target_height).saturating_sub(min_confirmations),
+                            u32::from(min_height),
+                        ));
+
+                        self.get_balance_at(account, anchor_height)
+                    })
+                    .transpose()?
+                    .unwrap_or_else(Amount::zero);
+
+                Ok((min_confirmations, balance))
+            })
+            .collect()
+    }
+
+    /// Returns the distinct addresses `account` has sent funds to, together with the
+    /// total amount sent to each.
+    ///
+    /// This is intended for building an address book of a user's past payees; it
+    /// excludes the wallet's own addresses, since a self-sent change output is not a
+    /// payment to another party.
+    fn get_sent_recipients(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<(RecipientAddress, Amount)>, Self::Error>;
+
     /// Returns the memo for a note.
     ///
     /// Implementations of this method must return an error if the note identifier
     /// does not appear in the backing data store.
     fn get_memo(&self, id_note: Self::NoteRef) -> Result<Memo, Self::Error>;
 
+    /// Returns whether a note has an associated memo, without decoding it.
+    ///
+    /// This is cheaper than calling [`WalletRead::get_memo`] and checking the result
+    /// against [`Memo::Empty`] when the caller only needs to know whether a memo is
+    /// present, such as for a list view that shows a memo icon.
+    ///
+    /// Implementations of this method must return an error if the note identifier
+    /// does not appear in the backing data store.
+    fn note_has_memo(&self, id_note: Self::NoteRef) -> Result<bool, Self::Error>;
+
+    /// Returns the memo attached to the sent note at `output_index` within the
+    /// transaction identified by `txid`, without requiring the caller to already have
+    /// that note's [`WalletRead::NoteRef`] on hand.
+    ///
+    /// Returns `Ok(Some(Memo::Empty))` if the note exists but has no memo recorded (a
+    /// `NULL` memo, e.g. for a transparent recipient), and `Ok(None)` if no sent note
+    /// matches `txid` and `output_index` at all.
+    fn get_sent_memo_for(
+        &self,
+        txid: TxId,
+        output_index: usize,
+    ) -> Result<Option<Memo>, Self::Error>;
+
+    /// Groups the memos exchanged with `account` by conversation counterparty, for
+    /// memo-based chat features built on top of the wallet.
+    ///
+    /// Sent notes are grouped by their recorded recipient address. Received notes are
+    /// grouped by a reply-to address parsed from the memo itself, per the convention of
+    /// a memo beginning with the sender's z-address; a received memo that does not
+    /// follow this convention is omitted, since a shielded note's sender is not
+    /// otherwise recoverable from the chain.
+    ///
+    /// This returns a list of `(counterparty, memos)` pairs rather than a map, as
+    /// [`RecipientAddress`] does not implement `Hash`.
+    fn get_memo_conversations(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<(RecipientAddress, Vec<Memo>)>, Self::Error>;
+
+    /// Returns the transaction that spent a note, or `Ok(None)` if the note is unspent.
+    ///
+    /// Implementations of this method must return an error if the note identifier
+    /// does not appear in the backing data store.
+    fn get_spending_tx(&self, note: Self::NoteRef) -> Result<Option<Self::TxRef>, Self::Error>;
+
+    /// Returns the user-supplied label for the transaction identified by `txid`, if one
+    /// has been set via [`WalletWrite::set_tx_label`], or `Ok(None)` otherwise.
+    fn get_tx_label(&self, txid: TxId) -> Result<Option<String>, Self::Error>;
+
+    /// Returns every note this wallet received in the transaction identified by `txid`,
+    /// for rendering a transaction detail view.
+    ///
+    /// Each entry is the note's identifier, value, memo (if any), and whether the note
+    /// is change produced by a transaction this wallet created. Returns an empty vector
+    /// if `txid` is unknown or received no notes belonging to this wallet.
+    fn get_received_notes_for_tx(
+        &self,
+        txid: TxId,
+    ) -> Result<Vec<(Self::NoteRef, Amount, Option<Memo>, bool)>, Self::Error>;
+
     /// Returns the note commitment tree at the specified block height.
     fn get_commitment_tree(
         &self,
         block_height: BlockHeight,
     ) -> Result<Option<CommitmentTree<Node>>, Self::Error>;
 
+    /// Returns the number of note commitments in the tree at the specified block
+    /// height, for sync progress and anchor selection.
+    ///
+    /// This default implementation deserializes and walks the tree returned by
+    /// [`WalletRead::get_commitment_tree`]; a store that additionally caches the leaf
+    /// count alongside the serialized tree can override this to avoid that
+    /// deserialization.
+    fn get_tree_size(&self, block_height: BlockHeight) -> Result<Option<u64>, Self::Error> {
+        self.get_commitment_tree(block_height)
+            .map(|tree_opt| tree_opt.map(|tree| tree.size() as u64))
+    }
+
     /// Returns the incremental witnesses as of the specified block height.
     #[allow(clippy::type_complexity)]
     fn get_witnesses(
@@ -155,25 +460,242 @@ pub trait WalletRead {
         block_height: BlockHeight,
     ) -> Result<Vec<(Self::NoteRef, IncrementalWitness<Node>)>, Self::Error>;
 
+    /// Returns the incremental witnesses as of the specified block height, for only the
+    /// given note identifiers.
+    ///
+    /// The default implementation filters the full result of [`WalletRead::get_witnesses`];
+    /// backends that index witnesses by note identifier should override this to avoid
+    /// loading and deserializing witnesses that the caller does not need.
+    #[allow(clippy::type_complexity)]
+    fn get_witnesses_for(
+        &self,
+        note_ids: &[Self::NoteRef],
+        block_height: BlockHeight,
+    ) -> Result<Vec<(Self::NoteRef, IncrementalWitness<Node>)>, Self::Error> {
+        Ok(self
+            .get_witnesses(block_height)?
+            .into_iter()
+            .filter(|(note_id, _)| note_ids.contains(note_id))
+            .collect())
+    }
+
+    /// Returns the incremental witnesses at the chain tip, together with the tip height
+    /// itself, so that a caller building an anchor does not need to determine the tip
+    /// height separately and risk it advancing between that call and
+    /// [`WalletRead::get_witnesses`].
+    ///
+    /// Returns `Ok(None)` if the wallet has not scanned any blocks yet.
+    ///
+    /// The default implementation is provided in terms of
+    /// [`WalletRead::get_max_scanned_height`] and [`WalletRead::get_witnesses`];
+    /// backends that can determine the two together (for example, within a single read
+    /// transaction) should override this to guarantee the height they return is exactly
+    /// the height their witnesses were computed against.
+    #[allow(clippy::type_complexity)]
+    fn get_tip_witnesses(
+        &self,
+    ) -> Result<Option<(BlockHeight, Vec<(Self::NoteRef, IncrementalWitness<Node>)>)>, Self::Error>
+    {
+        self.get_max_scanned_height()?
+            .map(|tip_height| Ok((tip_height, self.get_witnesses(tip_height)?)))
+            .transpose()
+    }
+
+    /// Returns the Sapling anchor (note commitment tree root) at the specified block
+    /// height, if the wallet has scanned a block at that height.
+    ///
+    /// The default implementation computes this from [`WalletRead::get_commitment_tree`],
+    /// and asserts (in debug builds) that it agrees with the root of every witness
+    /// returned by [`WalletRead::get_witnesses`] at that height, returning
+    /// [`Error::InvalidWitnessAnchor`] if not. This guards against the anchor mismatch
+    /// proof failures that result from building a transaction against witnesses that
+    /// were not actually computed against the tree at the claimed anchor height.
+    fn get_anchor(&self, anchor_height: BlockHeight) -> Result<Option<Node>, Self::Error>
+    where
+        Self::Error: From<error::Error<Self::NoteRef>>,
+    {
+        let anchor = match self.get_commitment_tree(anchor_height)? {
+            Some(tree) => tree.root(),
+            None => return Ok(None),
+        };
+
+        #[cfg(debug_assertions)]
+        for (note_id, witness) in self.get_witnesses(anchor_height)? {
+            if witness.root() != anchor {
+                return Err(error::Error::InvalidWitnessAnchor(note_id, anchor_height).into());
+            }
+        }
+
+        Ok(Some(anchor))
+    }
+
+    /// Recomputes and checks a single note's stored witness against the commitment
+    /// tree stored at `at_height`, returning `Ok(true)` if the witness root matches the
+    /// tree root and `Ok(false)` if it does not.
+    ///
+    /// This is a diagnostic to run by hand after a proof failure, to determine whether
+    /// the witness or the commitment tree is the inconsistent one: unlike
+    /// [`WalletRead::get_anchor`]'s debug-only assertion (which checks every witness at
+    /// a height as a side effect of computing the anchor), this targets one note on
+    /// demand and returns an error identifying whichever of the witness or the tree is
+    /// altogether missing, rather than only ever reporting a mismatch.
+    fn verify_witness(
+        &self,
+        note: Self::NoteRef,
+        at_height: BlockHeight,
+    ) -> Result<bool, Self::Error>;
+
     /// Returns the unspent nullifiers, along with the account identifiers
     /// with which they are associated.
     fn get_nullifiers(&self) -> Result<Vec<(AccountId, Nullifier)>, Self::Error>;
 
+    /// Returns the account and note identifier of the note controlled by this wallet
+    /// that is spent by the given nullifier, if any.
+    ///
+    /// This is a targeted, indexed lookup for checking a single incoming spend against
+    /// the wallet's notes, unlike [`WalletRead::get_nullifiers`] which returns the
+    /// entire unspent set for callers that need to check many nullifiers at once.
+    fn find_note_by_nullifier(
+        &self,
+        nf: &Nullifier,
+    ) -> Result<Option<(AccountId, Self::NoteRef)>, Self::Error>;
+
+    /// Returns nullifiers that are associated with more than one received note,
+    /// indicating that the wallet's data has become corrupted (for example, by two
+    /// accounts having been created from overlapping imported keys) and can no longer
+    /// reliably distinguish which account controls the underlying note.
+    ///
+    /// Callers should treat a non-empty result as a signal to warn the user and refuse
+    /// to build new transactions until the conflict has been resolved, since any
+    /// balance or note-selection computation involving these nullifiers may be double
+    /// counting a single note across accounts.
+    fn find_conflicting_nullifiers(&self) -> Result<Vec<Nullifier>, Self::Error>;
+
+    /// Checks the wallet's stored data for violations of the invariants that
+    /// [`WalletWrite::advance_by_block`] and note selection otherwise assume hold,
+    /// returning a list of [`IntegrityWarning`]s describing what was found rather than
+    /// failing on the first one encountered.
+    ///
+    /// This is intended to be cheap enough to run before building a transaction (for
+    /// example, from a UI's "check wallet health" action) rather than as a replacement
+    /// for a full rescan; a non-empty result should generally be treated as a signal
+    /// that a rescan from the affected height is needed.
+    fn check_integrity(&self) -> Result<Vec<IntegrityWarning<Self::NoteRef>>, Self::Error>;
+
     /// Return all spendable notes.
+    ///
+    /// If `verify` is `true`, each returned note's stored witness root is checked
+    /// against the commitment tree root at `anchor_height` before it is returned,
+    /// failing fast with the offending note id (mirroring [`WalletRead::get_anchor`]'s
+    /// debug-only check, but available unconditionally and per-call) rather than
+    /// letting a stale or corrupted witness reach proving.
     fn get_spendable_notes(
         &self,
         account: AccountId,
         anchor_height: BlockHeight,
+        verify: bool,
     ) -> Result<Vec<SpendableNote>, Self::Error>;
 
+    /// Returns a page of spendable notes, along with the total number of spendable
+    /// notes for the account, so that a caller with a huge note count (e.g. a merchant
+    /// wallet) can stream notes through a selection algorithm instead of holding them
+    /// all in memory at once.
+    ///
+    /// Notes are ordered by decreasing value, and ties are broken deterministically so
+    /// that repeated calls with the same arguments return pages that neither overlap
+    /// nor skip a note.
+    fn get_spendable_notes_paged(
+        &self,
+        account: AccountId,
+        anchor_height: BlockHeight,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<SpendableNote>, usize), Self::Error>;
+
+    /// Returns a page of spendable notes, paired with their note identifiers, ordered
+    /// by note identifier, starting after `after` (or from the beginning, if `after`
+    /// is `None`).
+    ///
+    /// Unlike [`WalletRead::get_spendable_notes_paged`], which pages by numeric offset,
+    /// this pages by a cursor into a stable ordering, so a caller scrolling through
+    /// notes (e.g. a coin-control UI) gets pages that neither repeat nor skip a note
+    /// even if new notes are received between calls. The returned identifiers let the
+    /// caller request the next page by passing the last one back in as `after`.
+    fn get_notes_page(
+        &self,
+        account: AccountId,
+        anchor_height: BlockHeight,
+        after: Option<Self::NoteRef>,
+        limit: usize,
+    ) -> Result<Vec<(Self::NoteRef, SpendableNote)>, Self::Error>;
+
     /// Returns a list of spendable notes sufficient to cover the specified
     /// target value, if possible.
+    ///
+    /// If `max_overselect` is `Some(cap)`, selection fails with
+    /// [`crate::data_api::error::Error::ExcessiveOverselection`] rather than returning
+    /// notes whose total exceeds `target_value` by more than `cap`.
+    ///
+    /// If `exclude_unmined_change` is `true`, notes that are both change (i.e. produced
+    /// by a transaction this wallet created) and whose source transaction has not yet
+    /// been mined are never selected, even if they would otherwise satisfy the anchor
+    /// height requirement; this avoids building a transaction that chains off of a note
+    /// that might never confirm.
+    ///
+    /// `exclude` lists note identifiers that must not be selected, without those notes
+    /// being marked spent in the wallet database. This lets a caller tentatively reserve
+    /// notes for a transaction it is still assembling (e.g. while planning several
+    /// transactions in a UI before committing any of them) so that a subsequent call
+    /// does not select the same notes again.
+    ///
+    /// `strategy` chooses which notes are preferred when more than one selection would
+    /// satisfy `target_value`; see [`NoteSelectionStrategy`] for the available options.
     fn select_spendable_notes(
         &self,
         account: AccountId,
         target_value: Amount,
         anchor_height: BlockHeight,
+        max_overselect: Option<Amount>,
+        exclude_unmined_change: bool,
+        exclude: &[Self::NoteRef],
+        strategy: NoteSelectionStrategy,
     ) -> Result<Vec<SpendableNote>, Self::Error>;
+
+    /// Returns the distribution of spendable note values for the given account as of the
+    /// specified anchor height, as `(value, count)` pairs.
+    ///
+    /// This is intended for surfacing wallet fragmentation to users (e.g. "you have 400
+    /// tiny notes, sending will be expensive") rather than for note selection, so unlike
+    /// [`WalletRead::get_spendable_notes`] it does not return witnesses or other data
+    /// needed to actually spend the notes.
+    fn get_note_value_distribution(
+        &self,
+        account: AccountId,
+        anchor_height: BlockHeight,
+    ) -> Result<Vec<(Amount, usize)>, Self::Error>;
+
+    /// Returns the transparent UTXOs received by the given address that are unspent as of
+    /// the specified anchor height.
+    #[cfg(feature = "transparent-inputs")]
+    fn get_spendable_transparent_utxos(
+        &self,
+        address: &TransparentAddress,
+        anchor_height: BlockHeight,
+    ) -> Result<Vec<WalletTransparentOutput>, Self::Error>;
+}
+
+/// A single row of the wallet's transaction history, as returned by
+/// [`WalletRead::get_transactions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletTransaction {
+    pub txid: TxId,
+    /// The height at which this transaction was mined, or `None` if it has not yet been
+    /// mined in the main chain.
+    pub block: Option<BlockHeight>,
+    /// The number of confirmations this transaction has as of the `tip_height` passed
+    /// to [`WalletRead::get_transactions`], or `None` if no `tip_height` was supplied.
+    /// An unmined transaction has `Some(0)` confirmations.
+    pub confirmations: Option<u32>,
 }
 
 /// The subset of information that is relevant to this wallet that has been
@@ -181,6 +703,10 @@ pub trait WalletRead {
 pub struct PrunedBlock<'a> {
     pub block_height: BlockHeight,
     pub block_hash: BlockHash,
+    /// The hash of the block immediately preceding this one, so that
+    /// [`WalletWrite::advance_by_block`] implementations can check for chain
+    /// continuity with the previously-persisted tip before inserting this block.
+    pub prev_hash: BlockHash,
     pub block_time: u32,
     pub commitment_tree: &'a CommitmentTree<Node>,
     pub transactions: &'a Vec<WalletTx<Nullifier>>,
@@ -215,25 +741,249 @@ pub struct SentTransaction<'a> {
     pub recipient_address: &'a RecipientAddress,
     pub value: Amount,
     pub memo: Option<MemoBytes>,
+    /// A client-supplied identifier linking this transaction back to the
+    /// [`crate::data_api::wallet::Proposal`] it was built from, for auditing purposes.
+    pub proposal_id: Option<String>,
+}
+
+/// Bookkeeping counts produced as a side effect of [`WalletWrite::advance_by_block`],
+/// intended for sync telemetry (e.g. progress UIs or fragmentation dashboards) rather
+/// than for correctness: callers that need the actual notes should use the returned
+/// witnesses or [`WalletRead::get_spendable_notes`] instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BlockAdvanceCounts {
+    /// The number of notes newly stored as a result of scanning this block.
+    pub notes_added: usize,
+    /// The number of previously-tracked notes that were marked spent (and so removed
+    /// from the spendable set) by transactions in this block.
+    pub notes_removed: usize,
+}
+
+/// A wallet invariant that [`WalletRead::check_integrity`] found to be violated.
+///
+/// These are the assumptions that [`WalletWrite::advance_by_block`] and note selection
+/// otherwise rely on implicitly (for example, [`SqliteClientError::WitnessMissing`]
+/// in `zcash_client_sqlite` reports one of these same conditions, but only at the
+/// moment a spend actually needs the missing witness). `check_integrity` surfaces them
+/// up front as a list of warnings rather than the first error encountered, so a caller
+/// can decide whether to trigger a rescan before attempting to build a transaction,
+/// rather than discovering the problem partway through.
+///
+/// [`SqliteClientError::WitnessMissing`]: https://docs.rs/zcash_client_sqlite
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntegrityWarning<NoteRef> {
+    /// A note the wallet received has no stored incremental witness at the wallet's
+    /// current tip, so it cannot currently be selected as a spend.
+    MissingWitnessAtTip { note: NoteRef, tip_height: BlockHeight },
+
+    /// A note recorded as spent refers to a spending transaction that is not present
+    /// in the `transactions`/history table. Since the wallet only ever marks a note
+    /// spent by a transaction it has itself observed or created, this indicates a
+    /// stored reference has gone stale (for example, because the spending
+    /// transaction's row was removed by a rewind without the note being unmarked).
+    DanglingSpend { note: NoteRef },
+
+    /// The wallet's stored blocks are not contiguous from its birthday height to its
+    /// chain tip; the first gap found is reported.
+    NonContiguousBlocks {
+        expected_height: BlockHeight,
+        found_height: BlockHeight,
+    },
+
+    /// A note's stored witness does not produce the same root as the note commitment
+    /// tree recorded for the height the witness was last updated at.
+    WitnessRootMismatch { note: NoteRef, height: BlockHeight },
 }
 
 /// This trait encapsulates the write capabilities required to update stored
 /// wallet data.
 pub trait WalletWrite: WalletRead {
+    /// Persists the given block, along with the witness updates produced by scanning it.
+    ///
+    /// Implementations must check `block.prev_hash` against the hash of the wallet's
+    /// current chain tip (if any) before inserting the block, and report an
+    /// implementation-specific "block conflict" error rather than inserting a block that
+    /// would fork the persisted chain.
     #[allow(clippy::type_complexity)]
     fn advance_by_block(
         &mut self,
         block: &PrunedBlock,
         updated_witnesses: &[(Self::NoteRef, IncrementalWitness<Node>)],
-    ) -> Result<Vec<(Self::NoteRef, IncrementalWitness<Node>)>, Self::Error>;
+    ) -> Result<
+        (
+            Vec<(Self::NoteRef, IncrementalWitness<Node>)>,
+            BlockAdvanceCounts,
+        ),
+        Self::Error,
+    >;
+
+    /// Advances the wallet's chain state by a contiguous slice of blocks, persisting the
+    /// result of each block as [`advance_by_block`] would.
+    ///
+    /// This exists to amortize the per-block transaction overhead of calling
+    /// [`advance_by_block`] in a loop: implementations may override this method to wrap
+    /// the whole slice in a single underlying database transaction and defer witness
+    /// pruning and expired-note bookkeeping until the last block has been processed,
+    /// rather than repeating that work once per block.
+    ///
+    /// Unlike [`advance_by_block`], which returns only the witnesses newly created for
+    /// the block just processed, this returns the full set of witnesses tracked after
+    /// processing every block in `blocks` (i.e. `updated_witnesses` plus every witness
+    /// created along the way) so that it can be passed directly as `updated_witnesses`
+    /// to a subsequent call.
+    ///
+    /// [`advance_by_block`]: WalletWrite::advance_by_block
+    #[allow(clippy::type_complexity)]
+    fn advance_by_blocks(
+        &mut self,
+        blocks: &[PrunedBlock],
+        updated_witnesses: &[(Self::NoteRef, IncrementalWitness<Node>)],
+    ) -> Result<
+        (
+            Vec<(Self::NoteRef, IncrementalWitness<Node>)>,
+            BlockAdvanceCounts,
+        ),
+        Self::Error,
+    > {
+        let mut witnesses = updated_witnesses.to_vec();
+        let mut counts = BlockAdvanceCounts::default();
+        for block in blocks {
+            let (new_witnesses, block_counts) = self.advance_by_block(block, &witnesses)?;
+            witnesses.extend(new_witnesses);
+            counts.notes_added += block_counts.notes_added;
+            counts.notes_removed += block_counts.notes_removed;
+        }
+        Ok((witnesses, counts))
+    }
+
+    /// Generates and persists the next available diversified payment address for the
+    /// specified account, advancing the account's diversifier index past the one used.
+    ///
+    /// Diversifier indices that do not correspond to a valid diversifier for the
+    /// account's incoming viewing key are skipped automatically. Returns
+    /// [`error::Error::AccountNotFound`] if the account identifier does not correspond
+    /// to a known account, or [`error::Error::DiversifierSpaceExhausted`] if no further
+    /// valid diversifier indices remain.
+    fn get_next_available_address(
+        &mut self,
+        account: AccountId,
+    ) -> Result<PaymentAddress, Self::Error>;
 
     fn store_received_tx(
         &mut self,
         received_tx: &ReceivedTransaction,
     ) -> Result<Self::TxRef, Self::Error>;
 
+    /// Persists each of `received_txs` as [`WalletWrite::store_received_tx`] would.
+    ///
+    /// This exists to amortize the per-transaction overhead of calling
+    /// `store_received_tx` in a loop when importing a large batch of transactions (for
+    /// example, a rescan from raw transaction data): implementations may override this
+    /// method to wrap the whole batch in a single underlying database transaction rather
+    /// than one per call. Each transaction's outputs are still classified as outgoing or
+    /// received exactly as `store_received_tx` would classify them individually.
+    fn store_received_txs(
+        &mut self,
+        received_txs: &[ReceivedTransaction],
+    ) -> Result<Vec<Self::TxRef>, Self::Error> {
+        received_txs
+            .iter()
+            .map(|received_tx| self.store_received_tx(received_tx))
+            .collect()
+    }
+
     fn store_sent_tx(&mut self, sent_tx: &SentTransaction) -> Result<Self::TxRef, Self::Error>;
 
+    /// Records the miner fee paid by the transaction identified by `txid`.
+    ///
+    /// [`WalletWrite::store_sent_tx`] calls this automatically once the fee can be
+    /// determined from the transaction's value balance (this is only possible when the
+    /// transaction has no transparent inputs, since a transparent input's value is not
+    /// itself recorded in the transaction); most callers therefore never need to call
+    /// this directly. It is exposed so that a caller which does know the fee some other
+    /// way (for example, from the [`Proposal`] used to build the transaction) can
+    /// backfill it regardless.
+    ///
+    /// [`Proposal`]: crate::data_api::wallet::Proposal
+    fn set_transaction_fee(&mut self, txid: TxId, fee: Amount) -> Result<(), Self::Error>;
+
+    /// Records a user-supplied label for the transaction identified by `txid`, such as a
+    /// private note describing its purpose (e.g. "rent payment").
+    ///
+    /// Unlike the rest of a transaction's data, a label is not derived from the chain, so
+    /// implementations must ensure it survives a rewind or rescan of the transaction it
+    /// is attached to.
+    fn set_tx_label(&mut self, txid: TxId, label: String) -> Result<(), Self::Error>;
+
+    /// Records the outcome of broadcasting the transaction identified by `tx_ref`
+    /// (previously recorded via [`WalletWrite::store_sent_tx`] or
+    /// `store_replacement_tx`) to the network.
+    ///
+    /// On success, implementations should record that the transaction was broadcast so
+    /// that this can be reflected to the user. On failure, implementations must unlock
+    /// any notes that were marked spent by `tx_ref` so that they become available for
+    /// selection by a subsequent call to build a replacement transaction, rather than
+    /// remaining locked until `tx_ref` expires unmined.
+    fn set_tx_broadcast(&mut self, tx_ref: Self::TxRef, success: bool) -> Result<(), Self::Error>;
+
+    /// Records `new` as a fee-bump replacement for the stuck transaction `old`, which
+    /// must have already been recorded via [`WalletWrite::store_sent_tx`] or
+    /// `store_replacement_tx` itself.
+    ///
+    /// This stores `new` exactly as `store_sent_tx` would, re-marking any notes `new`
+    /// spends as spent by `new` rather than `old`. Since a replacement transaction
+    /// spends the same inputs as the one it replaces, this also has the effect of
+    /// pointing those notes away from `old`, so that `old` expiring unmined does not
+    /// free inputs `new` still depends on.
+    fn store_replacement_tx(
+        &mut self,
+        old: Self::TxRef,
+        new: &SentTransaction,
+    ) -> Result<Self::TxRef, Self::Error>;
+
+    /// Records a transparent UTXO received by the wallet, so that it can later be
+    /// selected for shielding.
+    #[cfg(feature = "transparent-inputs")]
+    fn put_received_transparent_utxo(
+        &mut self,
+        utxo: &WalletTransparentOutput,
+    ) -> Result<Self::NoteRef, Self::Error>;
+
+    /// Adds a watch-only account to the wallet, tracked from the given birthday height.
+    ///
+    /// Unlike the setup performed by `init_accounts_table` in `zcash_client_sqlite`, which
+    /// requires an empty wallet and a complete, ordered list of accounts, this method may
+    /// be called incrementally on a wallet that already has accounts and historical data;
+    /// the new account is appended after the existing ones. The birthday height is stored
+    /// alongside the account so that callers can determine the earliest height from which
+    /// blocks need to be fetched and scanned to discover this account's funds; scanning
+    /// itself is driven by the caller via [`BlockSource::with_blocks`], so this method does
+    /// not itself cause any blocks to be skipped.
+    ///
+    /// [`BlockSource::with_blocks`]: crate::data_api::BlockSource::with_blocks
+    fn import_viewing_account(
+        &mut self,
+        extfvk: &ExtendedFullViewingKey,
+        birthday: BlockHeight,
+    ) -> Result<AccountId, Self::Error>;
+
+    /// Recomputes and stores the nullifier for each of `account`'s received notes that
+    /// does not yet have one, using `extfvk`, and returns how many notes were filled in.
+    ///
+    /// In `zcash_client_sqlite`, [`WalletWrite::import_viewing_account`] already requires
+    /// a full viewing key and `received_notes.nf` is a `NOT NULL` column, so under normal
+    /// operation there is nothing for this method to find: every stored note already has
+    /// its nullifier computed at scan time. It is provided as a defensive counterpart to
+    /// [`WalletWrite::import_viewing_account`] for store implementations that do allow a
+    /// note to be recorded before its nullifier is known (for instance, one that admits
+    /// incoming-viewing-key-only accounts); calling it against this crate's own store is
+    /// always safe, but a no-op. Notes that already have a nullifier are left untouched.
+    fn backfill_nullifiers(
+        &mut self,
+        account: AccountId,
+        extfvk: &ExtendedFullViewingKey,
+    ) -> Result<usize, Self::Error>;
+
     /// Rewinds the wallet database to the specified height.
     ///
     /// This method assumes that the state of the underlying data store is
@@ -248,6 +998,30 @@ pub trait WalletWrite: WalletRead {
     ///
     /// There may be restrictions on how far it is possible to rewind.
     fn rewind_to_height(&mut self, block_height: BlockHeight) -> Result<(), Self::Error>;
+
+    /// Deletes stored block metadata (including each block's commitment tree state) for
+    /// all blocks below `block_height`, to reclaim the disk space it occupies.
+    ///
+    /// This is a storage-management operation, distinct from the witness pruning that
+    /// [`WalletWrite::advance_by_block`] already performs as blocks are scanned: pruning
+    /// witnesses discards data that is no longer needed to spend notes, while this
+    /// discards block metadata that is no longer needed to service a rewind.
+    /// Implementations must refuse to prune within the retained reorg window (i.e. the
+    /// blocks a subsequent [`WalletWrite::rewind_to_height`] call could still be asked to
+    /// rewind to), so that pruning can never leave the wallet unable to service a rewind
+    /// it would otherwise have accepted.
+    fn prune_blocks_below(&mut self, block_height: BlockHeight) -> Result<(), Self::Error>;
+
+    /// Discards all scanned chain state, witnesses, and notes, leaving the wallet's
+    /// accounts (and their viewing keys) intact.
+    ///
+    /// Unlike [`rewind_to_height`], which only reverts a bounded number of blocks, this
+    /// unconditionally clears the wallet back to an unscanned state, so that a caller
+    /// whose chain state is corrupted beyond what a rewind can repair can trigger a full
+    /// rescan from each account's birthday height.
+    ///
+    /// [`rewind_to_height`]: WalletWrite::rewind_to_height
+    fn reset_sync_state(&mut self) -> Result<(), Self::Error>;
 }
 
 /// This trait provides sequential access to raw blockchain data via a callback-oriented
@@ -277,18 +1051,25 @@ pub mod testing {
         memo::Memo,
         merkle_tree::{CommitmentTree, IncrementalWitness},
         sapling::{Node, Nullifier, PaymentAddress},
-        transaction::{components::Amount, TxId},
+        transaction::{components::Amount, Transaction, TxId},
         zip32::ExtendedFullViewingKey,
     };
 
+    #[cfg(feature = "transparent-inputs")]
+    use zcash_primitives::legacy::TransparentAddress;
+
     use crate::{
+        address::RecipientAddress,
+        keys::UnifiedFullViewingKey,
         proto::compact_formats::CompactBlock,
-        wallet::{AccountId, SpendableNote},
+        wallet::{AccountId, NoteSelectionStrategy, SpendableNote},
     };
+    #[cfg(feature = "transparent-inputs")]
+    use crate::wallet::WalletTransparentOutput;
 
     use super::{
-        error::Error, BlockSource, PrunedBlock, ReceivedTransaction, SentTransaction, WalletRead,
-        WalletWrite,
+        error::Error, BlockAdvanceCounts, BlockSource, IntegrityWarning, PrunedBlock,
+        ReceivedTransaction, SentTransaction, WalletRead, WalletTransaction, WalletWrite,
     };
 
     pub struct MockBlockSource {}
@@ -320,6 +1101,14 @@ pub mod testing {
             Ok(None)
         }
 
+        fn get_wallet_birthday(&self) -> Result<Option<BlockHeight>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_max_scanned_height(&self) -> Result<Option<BlockHeight>, Self::Error> {
+            Ok(None)
+        }
+
         fn get_block_hash(
             &self,
             _block_height: BlockHeight,
@@ -327,20 +1116,58 @@ pub mod testing {
             Ok(None)
         }
 
+        fn get_tip_block_time(&self) -> Result<Option<u32>, Self::Error> {
+            Ok(None)
+        }
+
+        fn estimate_block_time(&self, _height: BlockHeight) -> Result<Option<u32>, Self::Error> {
+            Ok(None)
+        }
+
         fn get_tx_height(&self, _txid: TxId) -> Result<Option<BlockHeight>, Self::Error> {
             Ok(None)
         }
 
+        fn get_transaction(&self, _txid: TxId) -> Result<Option<Transaction>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_sent_tx_proposal_id(&self, _txid: TxId) -> Result<Option<String>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_transactions(
+            &self,
+            _limit: usize,
+            _offset: usize,
+            _tip_height: Option<BlockHeight>,
+        ) -> Result<Vec<WalletTransaction>, Self::Error> {
+            Ok(Vec::new())
+        }
+
         fn get_address(&self, _account: AccountId) -> Result<Option<PaymentAddress>, Self::Error> {
             Ok(None)
         }
 
+        fn get_current_address(
+            &self,
+            _account: AccountId,
+        ) -> Result<Option<PaymentAddress>, Self::Error> {
+            Ok(None)
+        }
+
         fn get_extended_full_viewing_keys(
             &self,
         ) -> Result<HashMap<AccountId, ExtendedFullViewingKey>, Self::Error> {
             Ok(HashMap::new())
         }
 
+        fn get_unified_full_viewing_keys(
+            &self,
+        ) -> Result<HashMap<AccountId, UnifiedFullViewingKey>, Self::Error> {
+            Ok(HashMap::new())
+        }
+
         fn is_valid_account_extfvk(
             &self,
             _account: AccountId,
@@ -357,10 +1184,58 @@ pub mod testing {
             Ok(Amount::zero())
         }
 
+        fn get_balances_at(
+            &self,
+            _anchor_height: BlockHeight,
+        ) -> Result<HashMap<AccountId, Amount>, Self::Error> {
+            Ok(HashMap::new())
+        }
+
+        fn get_sent_recipients(
+            &self,
+            _account: AccountId,
+        ) -> Result<Vec<(RecipientAddress, Amount)>, Self::Error> {
+            Ok(Vec::new())
+        }
+
         fn get_memo(&self, _id_note: Self::NoteRef) -> Result<Memo, Self::Error> {
             Ok(Memo::Empty)
         }
 
+        fn note_has_memo(&self, _id_note: Self::NoteRef) -> Result<bool, Self::Error> {
+            Ok(false)
+        }
+
+        fn get_sent_memo_for(
+            &self,
+            _txid: TxId,
+            _output_index: usize,
+        ) -> Result<Option<Memo>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_memo_conversations(
+            &self,
+            _account: AccountId,
+        ) -> Result<Vec<(RecipientAddress, Vec<Memo>)>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn get_spending_tx(&self, _note: Self::NoteRef) -> Result<Option<Self::TxRef>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_tx_label(&self, _txid: TxId) -> Result<Option<String>, Self::Error> {
+            Ok(None)
+        }
+
+        fn get_received_notes_for_tx(
+            &self,
+            _txid: TxId,
+        ) -> Result<Vec<(Self::NoteRef, Amount, Option<Memo>, bool)>, Self::Error> {
+            Ok(Vec::new())
+        }
+
         fn get_commitment_tree(
             &self,
             _block_height: BlockHeight,
@@ -376,26 +1251,91 @@ pub mod testing {
             Ok(Vec::new())
         }
 
+        fn verify_witness(
+            &self,
+            _note: Self::NoteRef,
+            _at_height: BlockHeight,
+        ) -> Result<bool, Self::Error> {
+            Ok(true)
+        }
+
         fn get_nullifiers(&self) -> Result<Vec<(AccountId, Nullifier)>, Self::Error> {
             Ok(Vec::new())
         }
 
+        fn find_note_by_nullifier(
+            &self,
+            _nf: &Nullifier,
+        ) -> Result<Option<(AccountId, Self::NoteRef)>, Self::Error> {
+            Ok(None)
+        }
+
+        fn find_conflicting_nullifiers(&self) -> Result<Vec<Nullifier>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        fn check_integrity(&self) -> Result<Vec<IntegrityWarning<Self::NoteRef>>, Self::Error> {
+            Ok(Vec::new())
+        }
+
         fn get_spendable_notes(
             &self,
             _account: AccountId,
             _anchor_height: BlockHeight,
+            _verify: bool,
         ) -> Result<Vec<SpendableNote>, Self::Error> {
             Ok(Vec::new())
         }
 
+        fn get_spendable_notes_paged(
+            &self,
+            _account: AccountId,
+            _anchor_height: BlockHeight,
+            _offset: usize,
+            _limit: usize,
+        ) -> Result<(Vec<SpendableNote>, usize), Self::Error> {
+            Ok((Vec::new(), 0))
+        }
+
+        fn get_notes_page(
+            &self,
+            _account: AccountId,
+            _anchor_height: BlockHeight,
+            _after: Option<Self::NoteRef>,
+            _limit: usize,
+        ) -> Result<Vec<(Self::NoteRef, SpendableNote)>, Self::Error> {
+            Ok(Vec::new())
+        }
+
         fn select_spendable_notes(
             &self,
             _account: AccountId,
             _target_value: Amount,
             _anchor_height: BlockHeight,
+            _max_overselect: Option<Amount>,
+            _exclude_unmined_change: bool,
+            _exclude: &[Self::NoteRef],
+            _strategy: NoteSelectionStrategy,
         ) -> Result<Vec<SpendableNote>, Self::Error> {
             Ok(Vec::new())
         }
+
+        fn get_note_value_distribution(
+            &self,
+            _account: AccountId,
+            _anchor_height: BlockHeight,
+        ) -> Result<Vec<(Amount, usize)>, Self::Error> {
+            Ok(Vec::new())
+        }
+
+        #[cfg(feature = "transparent-inputs")]
+        fn get_spendable_transparent_utxos(
+            &self,
+            _address: &TransparentAddress,
+            _anchor_height: BlockHeight,
+        ) -> Result<Vec<WalletTransparentOutput>, Self::Error> {
+            Ok(Vec::new())
+        }
     }
 
     impl WalletWrite for MockWalletDb {
@@ -404,8 +1344,14 @@ pub mod testing {
             &mut self,
             _block: &PrunedBlock,
             _updated_witnesses: &[(Self::NoteRef, IncrementalWitness<Node>)],
-        ) -> Result<Vec<(Self::NoteRef, IncrementalWitness<Node>)>, Self::Error> {
-            Ok(vec![])
+        ) -> Result<
+            (
+                Vec<(Self::NoteRef, IncrementalWitness<Node>)>,
+                BlockAdvanceCounts,
+            ),
+            Self::Error,
+        > {
+            Ok((vec![], BlockAdvanceCounts::default()))
         }
 
         fn store_received_tx(
@@ -422,8 +1368,67 @@ pub mod testing {
             Ok(TxId([0u8; 32]))
         }
 
+        fn store_replacement_tx(
+            &mut self,
+            _old: Self::TxRef,
+            _new: &SentTransaction,
+        ) -> Result<Self::TxRef, Self::Error> {
+            Ok(TxId([0u8; 32]))
+        }
+
+        fn set_transaction_fee(&mut self, _txid: TxId, _fee: Amount) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_tx_label(&mut self, _txid: TxId, _label: String) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn set_tx_broadcast(&mut self, _tx_ref: Self::TxRef, _success: bool) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        #[cfg(feature = "transparent-inputs")]
+        fn put_received_transparent_utxo(
+            &mut self,
+            _utxo: &WalletTransparentOutput,
+        ) -> Result<Self::NoteRef, Self::Error> {
+            Ok(0)
+        }
+
+        fn import_viewing_account(
+            &mut self,
+            _extfvk: &ExtendedFullViewingKey,
+            _birthday: BlockHeight,
+        ) -> Result<AccountId, Self::Error> {
+            Ok(AccountId(0))
+        }
+
+        fn get_next_available_address(
+            &mut self,
+            account: AccountId,
+        ) -> Result<PaymentAddress, Self::Error> {
+            Err(Error::AccountNotFound(account))
+        }
+
+        fn backfill_nullifiers(
+            &mut self,
+            _account: AccountId,
+            _extfvk: &ExtendedFullViewingKey,
+        ) -> Result<usize, Self::Error> {
+            Ok(0)
+        }
+
         fn rewind_to_height(&mut self, _block_height: BlockHeight) -> Result<(), Self::Error> {
             Ok(())
         }
+
+        fn prune_blocks_below(&mut self, _block_height: BlockHeight) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn reset_sync_state(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
     }
 }
@@ -85,11 +85,14 @@
 //! // At this point, the cache and scanned data are locally consistent (though not
 //! // necessarily consistent with the latest chain tip - this would be discovered the
 //! // next time this codepath is executed after new blocks are received).
-//! scan_cached_blocks(&network, &db_cache, &mut db_data, None)
+//! scan_cached_blocks(&network, &db_cache, &mut db_data, None)?;
+//! # Ok(())
 //! # }
 //! ```
 
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
 use zcash_primitives::{
     block::BlockHash,
@@ -105,7 +108,7 @@ use crate::{
         BlockSource, PrunedBlock, WalletWrite,
     },
     proto::compact_formats::CompactBlock,
-    wallet::{AccountId, WalletTx},
+    wallet::{AccountId, WalletShieldedOutput, WalletTx},
     welding_rig::scan_block,
 };
 
@@ -186,6 +189,10 @@ where
 /// function will process sequential ranges of blocks, and is equivalent to calling
 /// `scan_cached_blocks` and passing `None` for the optional `limit` value.
 ///
+/// On success, returns a [`ScanSummary`] describing the batch that was scanned, for
+/// callers that want to build sync progress telemetry without instrumenting the scan
+/// internals themselves.
+///
 /// This function pays attention only to cached blocks with heights greater than the
 /// highest scanned block in `data`. Cached blocks with lower heights are not verified
 /// against previously-scanned blocks. In particular, this function **assumes** that the
@@ -241,14 +248,119 @@ pub fn scan_cached_blocks<E, N, P, C, D>(
     cache: &C,
     data: &mut D,
     limit: Option<u32>,
-) -> Result<(), E>
+) -> Result<ScanSummary, E>
+where
+    P: consensus::Parameters,
+    C: BlockSource<Error = E>,
+    D: WalletWrite<Error = E, NoteRef = N>,
+    N: Copy + Debug,
+    E: From<Error<N>>,
+{
+    scan_cached_blocks_internal(params, cache, data, limit, |_| {}, None)
+}
+
+/// Scans new blocks added to the cache for any transactions received by the tracked
+/// accounts, stopping early if `cancelled` is set.
+///
+/// This behaves exactly as [`scan_cached_blocks`], except that `cancelled` is checked
+/// before each new block is scanned. If it is found to be set, this function returns
+/// [`Error::Canceled`] without scanning any further blocks; the block most recently
+/// passed to `advance_by_block` has already been fully committed to `data`, so the
+/// wallet database is left in a consistent, resumable state and a subsequent call to
+/// `scan_cached_blocks` (or this function again) will continue from where it left off.
+///
+/// This is intended for use by callers that need to stop a long-running scan promptly in
+/// response to some external event, such as a mobile app being backgrounded mid-sync.
+pub fn scan_cached_blocks_with_cancellation<E, N, P, C, D>(
+    params: &P,
+    cache: &C,
+    data: &mut D,
+    limit: Option<u32>,
+    cancelled: &AtomicBool,
+) -> Result<ScanSummary, E>
+where
+    P: consensus::Parameters,
+    C: BlockSource<Error = E>,
+    D: WalletWrite<Error = E, NoteRef = N>,
+    N: Copy + Debug,
+    E: From<Error<N>>,
+{
+    scan_cached_blocks_internal(params, cache, data, limit, |_| {}, Some(cancelled))
+}
+
+/// Scans new blocks added to the cache for any transactions received by the tracked
+/// accounts, and reports scan progress to a caller-supplied telemetry hook.
+///
+/// This behaves exactly as [`scan_cached_blocks`], except that `on_output` is invoked
+/// once for each wallet-relevant output as soon as it is decrypted, before that block's
+/// results are written to the wallet database. This lets a caller react to (e.g. surface
+/// a notification for) a received note immediately, without waiting for the whole batch
+/// of cached blocks to finish scanning.
+pub fn scan_cached_blocks_with_notify<E, N, P, C, D, F>(
+    params: &P,
+    cache: &C,
+    data: &mut D,
+    limit: Option<u32>,
+    on_output: F,
+) -> Result<ScanSummary, E>
 where
     P: consensus::Parameters,
     C: BlockSource<Error = E>,
     D: WalletWrite<Error = E, NoteRef = N>,
     N: Copy + Debug,
     E: From<Error<N>>,
+    F: FnMut(&WalletShieldedOutput<Nullifier>),
 {
+    scan_cached_blocks_internal(params, cache, data, limit, on_output, None)
+}
+
+/// Per-batch statistics returned by [`scan_cached_blocks`] and its variants.
+///
+/// This totals, over the whole batch, the blocks scanned and the wallet-relevant
+/// transactions, outputs and spends that block scanning found, along with the elapsed
+/// time, so a caller can build sync progress telemetry without instrumenting the scan
+/// internals itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanSummary {
+    /// The number of blocks scanned in this batch.
+    pub blocks_scanned: u32,
+    /// The number of transactions containing wallet-relevant shielded activity found
+    /// in the scanned blocks.
+    pub txs_found: usize,
+    /// The number of wallet outputs discovered across the scanned blocks.
+    pub outputs_found: usize,
+    /// The number of previously-tracked notes spent by transactions in the scanned
+    /// blocks.
+    pub spends_found: usize,
+    /// The wall-clock time taken to scan and persist this batch.
+    pub elapsed: std::time::Duration,
+}
+
+fn scan_cached_blocks_internal<E, N, P, C, D, F>(
+    params: &P,
+    cache: &C,
+    data: &mut D,
+    limit: Option<u32>,
+    mut on_output: F,
+    cancelled: Option<&AtomicBool>,
+) -> Result<ScanSummary, E>
+where
+    P: consensus::Parameters,
+    C: BlockSource<Error = E>,
+    D: WalletWrite<Error = E, NoteRef = N>,
+    N: Copy + Debug,
+    E: From<Error<N>>,
+    F: FnMut(&WalletShieldedOutput<Nullifier>),
+{
+    let start = Instant::now();
+    let mut summary = ScanSummary {
+        blocks_scanned: 0,
+        txs_found: 0,
+        outputs_found: 0,
+        spends_found: 0,
+        elapsed: Default::default(),
+    };
+
     let sapling_activation_height = params
         .activation_height(NetworkUpgrade::Sapling)
         .ok_or(Error::SaplingNotActive)?;
@@ -276,6 +388,12 @@ where
     let mut nullifiers = data.get_nullifiers()?;
 
     cache.with_blocks(last_height, limit, |block: CompactBlock| {
+        if let Some(cancelled) = cancelled {
+            if cancelled.load(Ordering::Relaxed) {
+                return Err(Error::Canceled.into());
+            }
+        }
+
         let current_height = block.height();
 
         // Scanned blocks MUST be height-sequential.
@@ -286,6 +404,7 @@ where
         }
 
         let block_hash = BlockHash::from_slice(&block.hash);
+        let block_prev_hash = block.prev_hash();
         let block_time = block.time;
 
         let txs: Vec<WalletTx<Nullifier>> = {
@@ -299,6 +418,7 @@ where
                 &mut tree,
                 &mut witness_refs[..],
             )
+            .map_err(Error::Scan)?
         };
 
         // Enforce that all roots match. This is slow, so only include in debug builds.
@@ -325,10 +445,28 @@ where
             }
         }
 
-        let new_witnesses = data.advance_by_block(
+        for tx in &txs {
+            for output in tx.shielded_outputs.iter() {
+                on_output(output);
+            }
+        }
+
+        summary.blocks_scanned += 1;
+        summary.txs_found += txs.len();
+        summary.outputs_found += txs
+            .iter()
+            .map(|tx| tx.shielded_outputs.len())
+            .sum::<usize>();
+        summary.spends_found += txs
+            .iter()
+            .map(|tx| tx.shielded_spends.len())
+            .sum::<usize>();
+
+        let (new_witnesses, _counts) = data.advance_by_block(
             &(PrunedBlock {
                 block_height: current_height,
                 block_hash,
+                prev_hash: block_prev_hash,
                 block_time,
                 commitment_tree: &tree,
                 transactions: &txs,
@@ -353,5 +491,7 @@ where
         Ok(())
     })?;
 
-    Ok(())
+    summary.elapsed = start.elapsed();
+
+    Ok(summary)
 }
@@ -9,6 +9,8 @@ use zcash_primitives::{
 };
 
 use crate::wallet::AccountId;
+use crate::welding_rig::ScanError;
+use crate::zip321::Zip321Error;
 
 #[derive(Debug)]
 pub enum ChainInvalid {
@@ -27,12 +29,20 @@ pub enum Error<NoteId> {
     /// Unable to create a new spend because the wallet balance is not sufficient.
     InsufficientBalance(Amount, Amount),
 
+    /// Note selection could not stay within the caller's requested overselection cap.
+    /// The first value is the amount by which the selected notes would have exceeded
+    /// the target value; the second is the cap that was violated.
+    ExcessiveOverselection(Amount, Amount),
+
     /// Chain validation detected an error in the block at the specified block height.
     InvalidChain(BlockHeight, ChainInvalid),
 
     /// A provided extsk is not associated with the specified account.
     InvalidExtSk(AccountId),
 
+    /// The account identifier does not correspond to an account known to the wallet.
+    AccountNotFound(AccountId),
+
     /// The root of an output's witness tree in a newly arrived transaction does
     /// not correspond to root of the stored commitment tree at the recorded height.
     ///
@@ -48,6 +58,9 @@ pub enum Error<NoteId> {
     /// operations can be performed.
     ScanRequired,
 
+    /// An error occurred while scanning a compact block.
+    Scan(ScanError),
+
     /// An error occurred building a new transaction.
     Builder(builder::Error),
 
@@ -57,6 +70,19 @@ pub enum Error<NoteId> {
     /// The wallet attempted a sapling-only operation at a block
     /// height when Sapling was not yet active.
     SaplingNotActive,
+
+    /// The diversifier index space for an account's incoming viewing key has been
+    /// exhausted while searching for the next valid diversified address.
+    DiversifierSpaceExhausted,
+
+    /// A ZIP 321 payment request URI could not be parsed, or requested a
+    /// structurally-invalid payment.
+    PaymentRequest(Zip321Error),
+
+    /// A long-running scan was stopped early in response to a caller-requested
+    /// cancellation. The wallet database is left in a consistent state as of the last
+    /// block that was fully scanned, and scanning may be resumed with a subsequent call.
+    Canceled,
 }
 
 impl ChainInvalid {
@@ -77,12 +103,20 @@ impl<N: fmt::Display> fmt::Display for Error<N> {
                 "Insufficient balance (have {}, need {} including fee)",
                 i64::from(*have), i64::from(*need)
             ),
+            Error::ExcessiveOverselection(overselected_by, cap) => write!(
+                f,
+                "Note selection would overselect by {}, exceeding the cap of {}",
+                i64::from(*overselected_by), i64::from(*cap)
+            ),
             Error::InvalidChain(upper_bound, cause) => {
                 write!(f, "Invalid chain (upper bound: {}): {:?}", u32::from(*upper_bound), cause)
             }
             Error::InvalidExtSk(account) => {
                 write!(f, "Incorrect ExtendedSpendingKey for account {}", account.0)
             }
+            Error::AccountNotFound(account) => {
+                write!(f, "No account found with id {}", account.0)
+            }
             Error::InvalidNewWitnessAnchor(output, txid, last_height, anchor) => write!(
                 f,
                 "New witness for output {} in tx {} has incorrect anchor after scanning block {}: {:?}",
@@ -94,9 +128,13 @@ impl<N: fmt::Display> fmt::Display for Error<N> {
                 id_note, last_height
             ),
             Error::ScanRequired => write!(f, "Must scan blocks first"),
+            Error::Scan(e) => write!(f, "{}", e),
             Error::Builder(e) => write!(f, "{:?}", e),
             Error::Protobuf(e) => write!(f, "{}", e),
             Error::SaplingNotActive => write!(f, "Could not determine Sapling upgrade activation height."),
+            Error::DiversifierSpaceExhausted => write!(f, "Diversifier space exhausted for this account's incoming viewing key."),
+            Error::PaymentRequest(e) => write!(f, "{}", e),
+            Error::Canceled => write!(f, "Scan was canceled before completion"),
         }
     }
 }
@@ -106,6 +144,8 @@ impl<N: error::Error + 'static> error::Error for Error<N> {
         match &self {
             Error::Builder(e) => Some(e),
             Error::Protobuf(e) => Some(e),
+            Error::Scan(e) => Some(e),
+            Error::PaymentRequest(e) => Some(e),
             _ => None,
         }
     }
@@ -122,3 +162,15 @@ impl<N> From<protobuf::ProtobufError> for Error<N> {
         Error::Protobuf(e)
     }
 }
+
+impl<N> From<ScanError> for Error<N> {
+    fn from(e: ScanError) -> Self {
+        Error::Scan(e)
+    }
+}
+
+impl<N> From<Zip321Error> for Error<N> {
+    fn from(e: Zip321Error) -> Self {
+        Error::PaymentRequest(e)
+    }
+}
@@ -1,9 +1,10 @@
 //! Functions for scanning the chain and extracting relevant information.
 use std::fmt::Debug;
+use std::str::FromStr;
 
 use zcash_primitives::{
-    consensus::{self, BranchId, NetworkUpgrade},
-    memo::MemoBytes,
+    consensus::{self, BlockHeight, BranchId, NetworkUpgrade},
+    memo::{self, Memo, MemoBytes},
     sapling::prover::TxProver,
     transaction::{
         builder::Builder,
@@ -13,15 +14,255 @@ use zcash_primitives::{
     zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
 };
 
+#[cfg(feature = "transparent-inputs")]
+use zcash_primitives::legacy::TransparentAddress;
+
 use crate::{
     address::RecipientAddress,
-    data_api::{error::Error, ReceivedTransaction, SentTransaction, WalletWrite},
+    data_api::{error::Error, ReceivedTransaction, SentTransaction, WalletRead, WalletWrite},
     decrypt_transaction,
-    wallet::{AccountId, OvkPolicy},
+    wallet::{AccountId, NoteSelectionStrategy, OvkPolicy, SpendableNote},
+    zip321::TransactionRequest,
 };
 
+#[cfg(feature = "transparent-inputs")]
+use crate::wallet::WalletTransparentOutput;
+
 pub const ANCHOR_OFFSET: u32 = 10;
 
+/// A minimal implementation of the [ZIP 317] conventional fee, used to size the input
+/// selection performed by [`create_spend_proposal`].
+///
+/// This wallet only constructs Sapling and transparent outputs, so a "logical action" is
+/// approximated here as one Sapling spend or output; this undercounts relative to the
+/// full ZIP 317 definition (which also accounts for transparent inputs/outputs and
+/// Orchard actions) but is conservative in the sense that it will never propose a fee
+/// lower than the network will accept for a Sapling-only transaction.
+///
+/// [ZIP 317]: https://zips.z.cash/zip-0317
+pub mod zip317 {
+    use zcash_primitives::transaction::components::Amount;
+
+    /// The number of logical actions that are not charged for, per ZIP 317.
+    pub const GRACE_ACTIONS: usize = 2;
+
+    /// The fee, in zatoshis, charged per logical action above the grace amount.
+    pub const MARGINAL_FEE: i64 = 5000;
+
+    /// Computes the ZIP 317 conventional fee for a transaction with the given number of
+    /// Sapling spends and outputs.
+    pub fn conventional_fee(n_spends: usize, n_outputs: usize) -> Amount {
+        let logical_actions = std::cmp::max(n_spends.max(n_outputs), GRACE_ACTIONS);
+        Amount::from_i64(MARGINAL_FEE * logical_actions as i64)
+            .expect("bounded by a small number of logical actions")
+    }
+}
+
+/// Constructs a [`MemoBytes`] value from a text body, optionally prefixed with a
+/// reply-to address so that the recipient can address a reply back to the sender
+/// without needing to already know it, per the convention parsed back out by
+/// `zcash_client_sqlite`'s `get_memo_conversations`.
+///
+/// Returns [`memo::Error::TooLong`] if the encoded memo -- the reply-to address, a
+/// separating space, and `body`, if a reply-to address is given, or just `body`
+/// otherwise -- would exceed the 512-byte memo field limit.
+pub fn build_memo<P: consensus::Parameters>(
+    params: &P,
+    body: &str,
+    reply_to: Option<&RecipientAddress>,
+) -> Result<MemoBytes, memo::Error> {
+    let text = match reply_to {
+        Some(addr) => format!("{} {}", addr.encode(params), body),
+        None => body.to_string(),
+    };
+
+    Memo::from_str(&text).map(MemoBytes::from)
+}
+
+/// A built, but not yet authorized, plan for spending funds from an account.
+///
+/// This is returned by [`create_spend_proposal`] so that callers can inspect the
+/// selected inputs, computed fee, and change amount before committing to building and
+/// signing the transaction.
+pub struct Proposal {
+    pub anchor_height: BlockHeight,
+    pub selected_notes: Vec<SpendableNote>,
+    pub payments: Vec<(RecipientAddress, Amount, Option<MemoBytes>)>,
+    pub change_value: Amount,
+    pub fee: Amount,
+}
+
+impl Proposal {
+    /// Returns the total value selected from the wallet's spendable notes.
+    pub fn selected_value(&self) -> Amount {
+        self.selected_notes
+            .iter()
+            .map(|n| n.note_value)
+            .sum::<Amount>()
+    }
+
+    /// Returns whether this proposal includes a change output.
+    pub fn has_change(&self) -> bool {
+        self.change_value > Amount::zero()
+    }
+}
+
+/// Builds a [`Proposal`] for spending from `account` to `payments`, selecting inputs
+/// with an iteratively-recomputed [ZIP 317] fee and including a single change output
+/// back to the account's own address when the selected notes exceed the payment total
+/// plus fee.
+///
+/// [ZIP 317]: https://zips.z.cash/zip-0317
+pub fn create_spend_proposal<E, N, D>(
+    wallet_db: &D,
+    account: AccountId,
+    payments: &[(RecipientAddress, Amount, Option<MemoBytes>)],
+    anchor_height: BlockHeight,
+) -> Result<Proposal, E>
+where
+    E: From<Error<N>>,
+    D: WalletRead<Error = E>,
+{
+    let payment_total = payments.iter().map(|(_, value, _)| *value).sum::<Amount>();
+
+    // Start from the fee for the payments alone, then iterate: each time the fee
+    // changes because the selected input/output count changed, reselect notes against
+    // the new target until the fee stabilizes.
+    let mut fee = zip317::conventional_fee(0, payments.len());
+    loop {
+        let target_value = payment_total + fee;
+        let selected_notes = wallet_db.select_spendable_notes(
+            account,
+            target_value,
+            anchor_height,
+            None,
+            true,
+            &[],
+            NoteSelectionStrategy::MinimizeInputs,
+        )?;
+        let selected_value = selected_notes.iter().map(|n| n.note_value).sum::<Amount>();
+
+        if selected_value < target_value {
+            return Err(E::from(Error::InsufficientBalance(
+                selected_value,
+                target_value,
+            )));
+        }
+
+        let change_value = selected_value - target_value;
+        let n_outputs = payments.len() + if change_value > Amount::zero() { 1 } else { 0 };
+        let new_fee = zip317::conventional_fee(selected_notes.len(), n_outputs);
+
+        if new_fee == fee {
+            return Ok(Proposal {
+                anchor_height,
+                selected_notes,
+                payments: payments.to_vec(),
+                change_value,
+                fee,
+            });
+        }
+
+        fee = new_fee;
+    }
+}
+
+/// Builds a [`Proposal`] for the payments requested by a [ZIP 321] URI, covering all of
+/// its payments with a single change output back to the account, in the same manner as
+/// [`create_spend_proposal`].
+///
+/// Parsing rejects a URI that associates a memo with a transparent recipient, since
+/// transparent outputs have no way to carry one. If the account's spendable notes at
+/// `anchor_height` do not cover the requested payments plus fee, this returns
+/// [`Error::InsufficientBalance`] with the amount selected and the amount required.
+///
+/// [ZIP 321]: https://zips.z.cash/zip-0321
+pub fn propose_from_payment_uri<E, N, D, P>(
+    wallet_db: &D,
+    params: &P,
+    account: AccountId,
+    uri: &str,
+    anchor_height: BlockHeight,
+) -> Result<Proposal, E>
+where
+    E: From<Error<N>>,
+    D: WalletRead<Error = E>,
+    P: consensus::Parameters,
+{
+    let request = TransactionRequest::from_uri(params, uri).map_err(|e| E::from(e.into()))?;
+
+    let payments = request
+        .payments()
+        .iter()
+        .map(|payment| {
+            (
+                payment.recipient_address.clone(),
+                payment.amount,
+                payment.memo.clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    create_spend_proposal(wallet_db, account, &payments, anchor_height)
+}
+
+/// A built, but not yet authorized, plan for shielding all of a transparent address's
+/// spendable UTXOs into a single Sapling note.
+///
+/// This is returned by [`propose_shielding`] so that callers can inspect the selected
+/// UTXOs and computed fee before committing to building and signing the transaction.
+#[cfg(feature = "transparent-inputs")]
+pub struct ShieldingProposal {
+    pub anchor_height: BlockHeight,
+    pub selected_utxos: Vec<WalletTransparentOutput>,
+    pub shielded_value: Amount,
+    pub fee: Amount,
+}
+
+#[cfg(feature = "transparent-inputs")]
+impl ShieldingProposal {
+    /// Returns the total value of the selected UTXOs.
+    pub fn selected_value(&self) -> Amount {
+        self.selected_utxos.iter().map(|u| u.value()).sum::<Amount>()
+    }
+}
+
+/// Builds a [`ShieldingProposal`] that consumes every UTXO currently spendable at
+/// `taddr` into a single shielded change output, less the [ZIP 317] fee for a
+/// transaction with one logical action per UTXO spent and a single Sapling output.
+///
+/// Unlike [`create_spend_proposal`], this is keyed by a transparent address rather than
+/// an account: the wallet does not currently maintain a mapping from accounts to the
+/// transparent addresses they control, so selection of "all of an account's UTXOs" is
+/// the caller's responsibility for each address they associate with the account.
+///
+/// [ZIP 317]: https://zips.z.cash/zip-0317
+#[cfg(feature = "transparent-inputs")]
+pub fn propose_shielding<E, N, D>(
+    wallet_db: &D,
+    taddr: &TransparentAddress,
+    anchor_height: BlockHeight,
+) -> Result<ShieldingProposal, E>
+where
+    E: From<Error<N>>,
+    D: WalletRead<Error = E>,
+{
+    let selected_utxos = wallet_db.get_spendable_transparent_utxos(taddr, anchor_height)?;
+    let selected_value = selected_utxos.iter().map(|u| u.value()).sum::<Amount>();
+    let fee = zip317::conventional_fee(selected_utxos.len(), 1);
+
+    if selected_value < fee {
+        return Err(E::from(Error::InsufficientBalance(selected_value, fee)));
+    }
+
+    Ok(ShieldingProposal {
+        anchor_height,
+        selected_utxos,
+        shielded_value: selected_value - fee,
+        fee,
+    })
+}
+
 /// Scans a [`Transaction`] for any information that can be decrypted by the accounts in
 /// the wallet, and saves it to the wallet.
 pub fn decrypt_and_store_transaction<N, E, P, D>(
@@ -184,7 +425,15 @@ where
         .and_then(|x| x.ok_or_else(|| Error::ScanRequired.into()))?;
 
     let target_value = value + DEFAULT_FEE;
-    let spendable_notes = wallet_db.select_spendable_notes(account, target_value, anchor_height)?;
+    let spendable_notes = wallet_db.select_spendable_notes(
+        account,
+        target_value,
+        anchor_height,
+        None,
+        true,
+        &[],
+        NoteSelectionStrategy::MinimizeInputs,
+    )?;
 
     // Confirm we were able to select sufficient value
     let selected_value = spendable_notes.iter().map(|n| n.note_value).sum();
@@ -254,5 +503,6 @@ where
         recipient_address: to,
         value,
         memo,
+        proposal_id: None,
     })
 }
@@ -11,7 +11,7 @@ use zcash_primitives::{
     zip32::ExtendedFullViewingKey,
 };
 
-use crate::wallet::AccountId;
+use crate::{keys::internal_extfvk, wallet::AccountId};
 
 /// A decrypted shielded output.
 pub struct DecryptedOutput {
@@ -32,29 +32,87 @@ pub struct DecryptedOutput {
     ///
     /// [`OutgoingViewingKey`]: zcash_primitives::sapling::keys::OutgoingViewingKey
     pub outgoing: bool,
+    /// True if this output was recovered using the account's *internal* (change) key,
+    /// rather than its external one, meaning it pays back to the wallet's own change
+    /// address rather than to some other recipient.
+    pub is_change: bool,
 }
 
 /// Scans a [`Transaction`] for any information that can be decrypted by the set of
 /// [`ExtendedFullViewingKey`]s.
+///
+/// This function is deterministic and does not itself require a CSPRNG: trial
+/// decryption derives all key material from `extfvks`, and the comparisons used to
+/// validate a trial decryption (the commitment and ephemeral-key checks performed by
+/// [`try_sapling_note_decryption`] and [`try_sapling_output_recovery`]) run in constant
+/// time with respect to the candidate output's contents, so that scanning a block
+/// containing attacker-crafted outputs does not leak which, if any, output matched via
+/// timing.
 pub fn decrypt_transaction<P: consensus::Parameters>(
     params: &P,
     height: BlockHeight,
     tx: &Transaction,
     extfvks: &HashMap<AccountId, ExtendedFullViewingKey>,
+) -> Vec<DecryptedOutput> {
+    decrypt_transaction_with_options(params, height, tx, extfvks, false)
+}
+
+/// As [`decrypt_transaction`], but with control over whether outgoing outputs are also
+/// trial-decrypted using each account's *internal* outgoing viewing key.
+///
+/// A transaction's outgoing outputs can only ever be recovered with the outgoing
+/// viewing key that was used to encrypt them; there is no way to recover them with no
+/// outgoing viewing key at all. What `try_recover_internal_ovk` controls is *which* of
+/// an account's two outgoing viewing keys are tried: when set, outputs that were sent
+/// using the account's internal (change) outgoing viewing key are also recovered. This
+/// matters when importing a wallet from seed, since a transaction created by another
+/// device sharing the same seed may have used the internal outgoing viewing key for an
+/// outgoing output (for example, a self-transfer between accounts of the same seed)
+/// that this device's own scan would otherwise be unable to attribute.
+pub fn decrypt_transaction_with_options<P: consensus::Parameters>(
+    params: &P,
+    height: BlockHeight,
+    tx: &Transaction,
+    extfvks: &HashMap<AccountId, ExtendedFullViewingKey>,
+    try_recover_internal_ovk: bool,
 ) -> Vec<DecryptedOutput> {
     let mut decrypted = vec![];
 
     for (account, extfvk) in extfvks.iter() {
         let ivk = extfvk.fvk.vk.ivk();
+        let internal_extfvk = internal_extfvk(extfvk);
+        let internal_ivk = internal_extfvk.fvk.vk.ivk();
         let ovk = extfvk.fvk.ovk;
+        let internal_ovk = internal_extfvk.fvk.ovk;
 
         for (index, output) in tx.shielded_outputs.iter().enumerate() {
-            let ((note, to, memo), outgoing) =
+            // Trial-decrypt with our own incoming viewing key first, then with the
+            // internal (change) incoming viewing key. Change (and any other
+            // self-payment) is always decryptable one of these two ways, so this
+            // ordering ensures such outputs are reported as received rather than
+            // outgoing, even though they were also recoverable via our outgoing
+            // viewing key.
+            let ((note, to, memo), outgoing, is_change) =
                 match try_sapling_note_decryption(params, height, &ivk, output) {
-                    Some(ret) => (ret, false),
-                    None => match try_sapling_output_recovery(params, height, &ovk, output) {
-                        Some(ret) => (ret, true),
-                        None => continue,
+                    Some(ret) => (ret, false, false),
+                    None => match try_sapling_note_decryption(params, height, &internal_ivk, output)
+                    {
+                        Some(ret) => (ret, false, true),
+                        None => match try_sapling_output_recovery(params, height, &ovk, output) {
+                            Some(ret) => (ret, true, false),
+                            None if try_recover_internal_ovk => {
+                                match try_sapling_output_recovery(
+                                    params,
+                                    height,
+                                    &internal_ovk,
+                                    output,
+                                ) {
+                                    Some(ret) => (ret, true, true),
+                                    None => continue,
+                                }
+                            }
+                            None => continue,
+                        },
                     },
                 };
             decrypted.push(DecryptedOutput {
@@ -64,6 +122,7 @@ pub fn decrypt_transaction<P: consensus::Parameters>(
                 to,
                 memo,
                 outgoing,
+                is_change,
             })
         }
     }
@@ -8,13 +8,18 @@
 use bech32::{self, Error, FromBase32, ToBase32, Variant};
 use bs58::{self, decode::Error as Bs58Error};
 use std::convert::TryInto;
+use std::fmt;
 use std::io::{self, Write};
 use zcash_primitives::{
+    consensus,
     legacy::TransparentAddress,
     sapling::{keys::OutgoingViewingKey, PaymentAddress},
+    transaction::components::{amount::COIN, Amount},
     zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
 };
 
+use crate::keys::UnifiedFullViewingKey;
+
 fn bech32_encode<F>(hrp: &str, write: F) -> String
 where
     F: Fn(&mut dyn Write) -> io::Result<()>,
@@ -24,6 +29,41 @@ where
     bech32::encode(hrp, data.to_base32(), Variant::Bech32).expect("hrp is invalid")
 }
 
+/// Errors that can occur while decoding a Bech32-encoded Zcash address.
+#[derive(Debug, PartialEq, Eq)]
+pub enum AddressError {
+    /// The string being decoded is not valid Bech32, or did not match the expected HRP.
+    Bech32(Error),
+    /// The decoded payload does not have the length expected for this address type.
+    InvalidLength(usize),
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressError::Bech32(e) => write!(f, "{}", e),
+            AddressError::InvalidLength(len) => {
+                write!(f, "decoded address payload has invalid length {}", len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AddressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AddressError::Bech32(e) => Some(e),
+            AddressError::InvalidLength(_) => None,
+        }
+    }
+}
+
+impl From<Error> for AddressError {
+    fn from(e: Error) -> Self {
+        AddressError::Bech32(e)
+    }
+}
+
 fn bech32_decode<T, F>(hrp: &str, s: &str, read: F) -> Result<Option<T>, Error>
 where
     F: Fn(Vec<u8>) -> Option<T>,
@@ -100,6 +140,39 @@ pub fn decode_extended_full_viewing_key(
     bech32_decode(hrp, s, |data| ExtendedFullViewingKey::read(&data[..]).ok())
 }
 
+/// Writes a [`UnifiedFullViewingKey`] as a Bech32-encoded string, using the network's
+/// Sapling extended full viewing key HRP.
+///
+/// Since this wallet only supports a single (Sapling) receiver, the encoding is
+/// currently identical to [`encode_extended_full_viewing_key`] applied to the unified
+/// key's Sapling component; this may change if support for additional protocols is
+/// added.
+///
+/// [`UnifiedFullViewingKey`]: crate::keys::UnifiedFullViewingKey
+pub fn encode_unified_full_viewing_key<P: consensus::Parameters>(
+    params: &P,
+    ufvk: &UnifiedFullViewingKey,
+) -> String {
+    encode_extended_full_viewing_key(
+        params.hrp_sapling_extended_full_viewing_key(),
+        ufvk.sapling(),
+    )
+}
+
+/// Decodes a [`UnifiedFullViewingKey`] from a Bech32-encoded string.
+///
+/// [`UnifiedFullViewingKey`]: crate::keys::UnifiedFullViewingKey
+pub fn decode_unified_full_viewing_key<P: consensus::Parameters>(
+    params: &P,
+    s: &str,
+) -> Result<Option<UnifiedFullViewingKey>, Error> {
+    let sapling = decode_extended_full_viewing_key(
+        params.hrp_sapling_extended_full_viewing_key(),
+        s,
+    )?;
+    Ok(sapling.map(UnifiedFullViewingKey::from_sapling_extended_full_viewing_key))
+}
+
 pub fn decode_outgoing_viewing_key(
     hrp: &str,
     s: &str,
@@ -188,16 +261,20 @@ pub fn encode_payment_address(hrp: &str, addr: &PaymentAddress) -> String {
 /// );
 /// ```
 /// [`PaymentAddress`]: zcash_primitives::sapling::PaymentAddress
-pub fn decode_payment_address(hrp: &str, s: &str) -> Result<Option<PaymentAddress>, Error> {
-    bech32_decode(hrp, s, |data| {
-        if data.len() != 43 {
-            return None;
+pub fn decode_payment_address(hrp: &str, s: &str) -> Result<Option<PaymentAddress>, AddressError> {
+    match bech32::decode(s)? {
+        (decoded_hrp, data, Variant::Bech32) if decoded_hrp == hrp => {
+            let bytes = Vec::<u8>::from_base32(&data)?;
+            if bytes.len() != 43 {
+                return Err(AddressError::InvalidLength(bytes.len()));
+            }
+
+            let mut tmp = [0; 43];
+            tmp.copy_from_slice(&bytes);
+            Ok(PaymentAddress::from_bytes(&tmp))
         }
-
-        let mut bytes = [0; 43];
-        bytes.copy_from_slice(&data);
-        PaymentAddress::from_bytes(&bytes)
-    })
+        _ => Ok(None),
+    }
 }
 
 /// Writes a [`TransparentAddress`] as a Base58Check-encoded string.
@@ -308,8 +385,50 @@ pub fn decode_transparent_address(
     })
 }
 
+/// Converts an [`Amount`] of zatoshis into its decimal ZEC value as an `f64`, for
+/// display purposes such as charts.
+///
+/// `f64` cannot exactly represent every zatoshi amount once divided down to ZEC, so this
+/// conversion is lossy: round-tripping a value through [`zec_f64_to_amount`] is only
+/// guaranteed to reproduce the original amount to within one zatoshi, not exactly.
+pub fn amount_to_zec_f64(amount: Amount) -> f64 {
+    i64::from(amount) as f64 / COIN as f64
+}
+
+/// Converts a decimal ZEC value into an [`Amount`] of zatoshis.
+///
+/// The value is scaled up to zatoshis and rounded to the nearest integer, with ties
+/// (an exact `x.5` zatoshi) rounded to the nearest even zatoshi, so that repeated
+/// rounding of values produced by [`amount_to_zec_f64`] does not accumulate a
+/// systematic bias in either direction. Returns `Err(())` if `zec` is not finite, or if
+/// the rounded zatoshi amount falls outside the range representable by [`Amount`].
+pub fn zec_f64_to_amount(zec: f64) -> Result<Amount, ()> {
+    if !zec.is_finite() {
+        return Err(());
+    }
+
+    let zatoshis = zec * COIN as f64;
+    if !zatoshis.is_finite() {
+        return Err(());
+    }
+
+    Amount::from_i64(round_half_even(zatoshis) as i64)
+}
+
+/// Rounds `x` to the nearest integer, with ties rounded to the nearest even integer.
+fn round_half_even(x: f64) -> f64 {
+    let floor = x.floor();
+    match x - floor {
+        diff if diff < 0.5 => floor,
+        diff if diff > 0.5 => floor + 1.0,
+        _ if (floor as i64) % 2 == 0 => floor,
+        _ => floor + 1.0,
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use bech32::{ToBase32, Variant};
     use group::Group;
     use rand_core::SeedableRng;
     use rand_xorshift::XorShiftRng;
@@ -319,9 +438,12 @@ mod tests {
         zip32::ExtendedSpendingKey,
     };
 
+    use zcash_primitives::transaction::components::Amount;
+
     use super::{
-        decode_extended_full_viewing_key, decode_extended_spending_key, decode_payment_address,
-        encode_extended_full_viewing_key, encode_extended_spending_key, encode_payment_address,
+        amount_to_zec_f64, decode_extended_full_viewing_key, decode_extended_spending_key,
+        decode_payment_address, encode_extended_full_viewing_key, encode_extended_spending_key,
+        encode_payment_address, zec_f64_to_amount, AddressError,
     };
     use crate::encoding::decode_outgoing_viewing_key;
 
@@ -472,10 +594,65 @@ mod tests {
         );
     }
 
+    #[test]
+    fn payment_address_invalid_length() {
+        let encoded = bech32::encode(
+            constants::mainnet::HRP_SAPLING_PAYMENT_ADDRESS,
+            vec![0u8; 42].to_base32(),
+            Variant::Bech32,
+        )
+        .unwrap();
+
+        match decode_payment_address(constants::mainnet::HRP_SAPLING_PAYMENT_ADDRESS, &encoded) {
+            Err(AddressError::InvalidLength(len)) => assert_eq!(len, 42),
+            other => panic!("expected AddressError::InvalidLength, got {:?}", other),
+        }
+    }
+
     #[test]
     fn outgoing_viewing_key() {
         let key = "zivks1nu0e48jup3ecdzmlf523w5fja9yshxjr8jr7t9h8ngjd4rff7upse40mtg";
         let hrp = "zivks";
         let _key = decode_outgoing_viewing_key(hrp, key).unwrap().unwrap();
     }
+
+    #[test]
+    fn zec_f64_round_trip_within_one_zatoshi() {
+        for zatoshis in [0i64, 1, -1, 12345678, -12345678, 21_000_000 * 1_0000_0000] {
+            let amount = Amount::from_i64(zatoshis).unwrap();
+            let zec = amount_to_zec_f64(amount);
+            let round_tripped = i64::from(zec_f64_to_amount(zec).unwrap());
+            assert!(
+                (round_tripped - zatoshis).abs() <= 1,
+                "expected {} to round-trip to within one zatoshi of itself, got {}",
+                zatoshis,
+                round_tripped
+            );
+        }
+    }
+
+    #[test]
+    fn zec_f64_to_amount_rounds_half_to_even() {
+        // 0.000000005 ZEC and 0.000000045 ZEC scale to exactly 0.5 and 4.5 zatoshis;
+        // ties round to the nearest even zatoshi, so both round down to their even
+        // neighbour (0 and 4) rather than up.
+        assert_eq!(
+            zec_f64_to_amount(0.000000005),
+            Ok(Amount::from_i64(0).unwrap())
+        );
+        assert_eq!(
+            zec_f64_to_amount(0.000000045),
+            Ok(Amount::from_i64(4).unwrap())
+        );
+    }
+
+    #[test]
+    fn zec_f64_to_amount_rejects_out_of_range() {
+        assert_eq!(zec_f64_to_amount(f64::NAN), Err(()));
+        assert_eq!(zec_f64_to_amount(f64::INFINITY), Err(()));
+        assert_eq!(zec_f64_to_amount(f64::NEG_INFINITY), Err(()));
+        assert_eq!(zec_f64_to_amount(21_000_001.0), Err(()));
+        assert_eq!(zec_f64_to_amount(-21_000_001.0), Err(()));
+        assert_eq!(zec_f64_to_amount(f64::MAX), Err(()));
+    }
 }
@@ -1,6 +1,65 @@
 //! Helper functions for managing light client key material.
 
-use zcash_primitives::zip32::{ChildIndex, ExtendedSpendingKey};
+use zcash_primitives::zip32::{ChildIndex, ExtendedFullViewingKey, ExtendedSpendingKey};
+
+#[cfg(feature = "transparent-inputs")]
+use {
+    ripemd160::Ripemd160,
+    sha2::{Digest, Sha256},
+    zcash_primitives::legacy::TransparentAddress,
+};
+
+/// A set of viewing keys that may be used to view a single account's incoming and
+/// outgoing transactions.
+///
+/// This wallet only supports the Sapling protocol, so unlike a full [ZIP 316] unified
+/// full viewing key (which may bundle receivers for several protocols behind a single
+/// encoding), this wraps a bare Sapling [`ExtendedFullViewingKey`]. It exists so that
+/// callers restoring a wallet have a single key type to pass around, and so that this
+/// crate has a natural extension point if support for additional protocols is added.
+///
+/// [ZIP 316]: https://zips.z.cash/zip-0316
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnifiedFullViewingKey {
+    sapling: ExtendedFullViewingKey,
+}
+
+impl UnifiedFullViewingKey {
+    /// Constructs a [`UnifiedFullViewingKey`] from its Sapling component.
+    pub fn from_sapling_extended_full_viewing_key(sapling: ExtendedFullViewingKey) -> Self {
+        UnifiedFullViewingKey { sapling }
+    }
+
+    /// Returns the Sapling component of this unified full viewing key.
+    pub fn sapling(&self) -> &ExtendedFullViewingKey {
+        &self.sapling
+    }
+}
+
+/// The ZIP 32 non-hardened child index reserved for deriving an account's internal
+/// (change) extended full viewing key from its external one.
+///
+/// This is the largest index available for non-hardened derivation, chosen so that it
+/// cannot collide with an index a wallet would use for an ordinary diversified receiving
+/// address.
+const INTERNAL_KEY_INDEX: u32 = (1 << 31) - 1;
+
+/// Derives the internal (change) [`ExtendedFullViewingKey`] for an account from its
+/// external [`ExtendedFullViewingKey`], per [ZIP 32]'s non-hardened derivation.
+///
+/// Wallets should use the internal key, rather than the external one, both to detect
+/// change outputs in [`decrypt_transaction`] and to compute the address returned by
+/// [`WalletRead::get_change_address`], so that a recipient scanning outgoing
+/// transactions cannot distinguish change from a payment to another party.
+///
+/// [ZIP 32]: https://zips.z.cash/zip-0032
+/// [`decrypt_transaction`]: crate::decrypt::decrypt_transaction
+/// [`WalletRead::get_change_address`]: crate::data_api::WalletRead::get_change_address
+pub fn internal_extfvk(extfvk: &ExtendedFullViewingKey) -> ExtendedFullViewingKey {
+    extfvk
+        .derive_child(ChildIndex::NonHardened(INTERNAL_KEY_INDEX))
+        .expect("non-hardened derivation from a valid ExtendedFullViewingKey cannot fail")
+}
 
 /// Derives the ZIP 32 [`ExtendedSpendingKey`] for a given coin type and account from the
 /// given seed.
@@ -33,13 +92,104 @@ pub fn spending_key(seed: &[u8], coin_type: u32, account: u32) -> ExtendedSpendi
     )
 }
 
+/// Derives a gap-limit set of external transparent receiving addresses for an account,
+/// for use in watching a range of addresses for incoming transparent funds ahead of a
+/// shielding transaction.
+///
+/// This crate has no `UnifiedSpendingKey` type, nor a ZIP 32 transparent key hierarchy
+/// comparable to the Sapling one exposed via [`spending_key`] and [`internal_extfvk`];
+/// transparent-inputs support today only covers tracking outputs a caller has already
+/// discovered (see [`crate::wallet::WalletTransparentOutput`]), not deriving the
+/// addresses to watch for them. Until a proper external transparent chain exists, each
+/// address here is derived deterministically from the same `(seed, coin_type, account)`
+/// triple as [`spending_key`], keyed additionally on the child index, so that a caller
+/// gets a stable, reproducible window of addresses to watch; this is not a standards
+/// track BIP 44 derivation, and addresses returned here will not match those produced by
+/// a wallet that later adopts a real transparent key hierarchy.
+///
+/// # Panics
+///
+/// Panics if `seed` is shorter than 32 bytes.
+#[cfg(feature = "transparent-inputs")]
+pub fn derive_transparent_addresses(
+    seed: &[u8],
+    coin_type: u32,
+    account: u32,
+    gap_limit: u32,
+) -> Vec<TransparentAddress> {
+    if seed.len() < 32 {
+        panic!("ZIP 32 seeds MUST be at least 32 bytes");
+    }
+
+    (0..gap_limit)
+        .map(|index| {
+            let mut hasher = Sha256::new();
+            hasher.update(seed);
+            hasher.update(&coin_type.to_le_bytes());
+            hasher.update(&account.to_le_bytes());
+            hasher.update(&index.to_le_bytes());
+            let sk = secp256k1::SecretKey::from_slice(&hasher.finalize())
+                .expect("SHA-256 digest is a valid secp256k1 scalar with overwhelming probability");
+
+            let secp = secp256k1::Secp256k1::signing_only();
+            let pk = secp256k1::PublicKey::from_secret_key(&secp, &sk);
+
+            let mut pubkey_hash = [0; 20];
+            pubkey_hash.copy_from_slice(&Ripemd160::digest(&Sha256::digest(&pk.serialize())));
+            TransparentAddress::PublicKey(pubkey_hash)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::spending_key;
+    use zcash_primitives::zip32::ExtendedFullViewingKey;
+
+    use super::{internal_extfvk, spending_key};
 
     #[test]
     #[should_panic]
     fn spending_key_panics_on_short_seed() {
         let _ = spending_key(&[0; 31][..], 0, 0);
     }
+
+    #[test]
+    fn internal_extfvk_default_address_differs_from_external() {
+        let extsk = spending_key(&[0; 32][..], 0, 0);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        let internal = internal_extfvk(&extfvk);
+
+        assert_ne!(
+            extfvk.default_address().unwrap().1,
+            internal.default_address().unwrap().1
+        );
+    }
+
+    #[cfg(feature = "transparent-inputs")]
+    #[test]
+    fn derive_transparent_addresses_is_deterministic_and_covers_the_gap_limit() {
+        use super::derive_transparent_addresses;
+
+        let addrs = derive_transparent_addresses(&[0; 32][..], 0, 0, 5);
+        assert_eq!(addrs.len(), 5);
+
+        // Every address in the window is distinct.
+        let mut sorted = addrs.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        sorted.dedup();
+        assert_eq!(sorted.len(), addrs.len());
+
+        // Re-deriving from the same seed, coin type, and account reproduces the window.
+        assert_eq!(derive_transparent_addresses(&[0; 32][..], 0, 0, 5), addrs);
+
+        // A different account derives an unrelated window.
+        assert_ne!(derive_transparent_addresses(&[0; 32][..], 0, 1, 5), addrs);
+    }
+
+    #[cfg(feature = "transparent-inputs")]
+    #[test]
+    #[should_panic]
+    fn derive_transparent_addresses_panics_on_short_seed() {
+        let _ = super::derive_transparent_addresses(&[0; 31][..], 0, 0, 5);
+    }
 }
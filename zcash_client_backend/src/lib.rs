@@ -18,4 +18,4 @@ pub mod wallet;
 pub mod welding_rig;
 pub mod zip321;
 
-pub use decrypt::{decrypt_transaction, DecryptedOutput};
+pub use decrypt::{decrypt_transaction, decrypt_transaction_with_options, DecryptedOutput};
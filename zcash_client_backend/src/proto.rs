@@ -8,7 +8,10 @@ use zcash_primitives::{
     block::{BlockHash, BlockHeader},
     consensus::BlockHeight,
     sapling::Nullifier,
-    transaction::components::sapling::{CompactOutputDescription, OutputDescription},
+    transaction::{
+        components::sapling::{CompactOutputDescription, OutputDescription},
+        Transaction,
+    },
 };
 
 use zcash_note_encryption::COMPACT_NOTE_SIZE;
@@ -72,6 +75,49 @@ impl compact_formats::CompactBlock {
             BlockHeader::read(&self.header[..]).ok()
         }
     }
+
+    /// Builds a [`CompactBlock`] at the given height from a set of transactions,
+    /// extracting the Sapling nullifiers and outputs relevant to note scanning.
+    ///
+    /// `zcash_primitives` in this tree does not expose a full block container (only
+    /// [`BlockHeader`]), so this takes the transactions directly rather than a `Block`;
+    /// a test server that already has a block's transactions (for example, from a full
+    /// node) can pass them here instead of hand-assembling compact structures.
+    ///
+    /// Transactions with no Sapling spends or outputs are omitted, matching the
+    /// behaviour of a real lightwalletd.
+    pub fn from_transactions<'a>(
+        height: BlockHeight,
+        hash: BlockHash,
+        prev_hash: BlockHash,
+        transactions: impl IntoIterator<Item = &'a Transaction>,
+    ) -> Self {
+        let mut cb = compact_formats::CompactBlock::new();
+        cb.set_height(u64::from(height));
+        cb.hash = hash.0.to_vec();
+        cb.prevHash = prev_hash.0.to_vec();
+
+        for tx in transactions {
+            let mut ctx = compact_formats::CompactTx::new();
+            ctx.set_hash(tx.txid().0.to_vec());
+
+            for spend in tx.shielded_spends.iter() {
+                let mut cspend = compact_formats::CompactSpend::new();
+                cspend.set_nf(spend.nullifier.to_vec());
+                ctx.spends.push(cspend);
+            }
+
+            for output in tx.shielded_outputs.iter() {
+                ctx.outputs.push(output.clone().into());
+            }
+
+            if !ctx.spends.is_empty() || !ctx.outputs.is_empty() {
+                cb.vtx.push(ctx);
+            }
+        }
+
+        cb
+    }
 }
 
 impl compact_formats::CompactOutput {
@@ -128,3 +174,97 @@ impl compact_formats::CompactSpend {
         Nullifier::from_slice(&self.nf).map_err(|_| ())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ff::Field;
+    use rand_core::OsRng;
+
+    use zcash_primitives::{
+        block::BlockHash,
+        consensus::BlockHeight,
+        sapling::{
+            redjubjub::{PublicKey, Signature},
+            Nullifier,
+        },
+        transaction::components::{
+            sapling::{OutputDescription, SpendDescription},
+            Amount, GROTH_PROOF_SIZE,
+        },
+        transaction::TransactionData,
+    };
+
+    use super::compact_formats::CompactBlock;
+
+    #[test]
+    fn from_transactions_extracts_spends_and_outputs() {
+        let mut rng = OsRng;
+
+        let nf = Nullifier([7; 32]);
+        let spend = SpendDescription {
+            cv: jubjub::ExtendedPoint::identity(),
+            anchor: bls12_381::Scalar::random(&mut rng),
+            nullifier: nf,
+            rk: PublicKey(jubjub::ExtendedPoint::identity()),
+            zkproof: [0; GROTH_PROOF_SIZE],
+            spend_auth_sig: Some(Signature::read(&[0u8; 64][..]).unwrap()),
+        };
+
+        let cmu = bls12_381::Scalar::random(&mut rng);
+        let ephemeral_key = jubjub::ExtendedPoint::identity();
+        let mut enc_ciphertext = [0; 580];
+        rand_core::RngCore::fill_bytes(&mut rng, &mut enc_ciphertext);
+        let output = OutputDescription {
+            cv: jubjub::ExtendedPoint::identity(),
+            cmu,
+            ephemeral_key,
+            enc_ciphertext,
+            out_ciphertext: [0; 80],
+            zkproof: [0; GROTH_PROOF_SIZE],
+        };
+
+        let mut data = TransactionData::new();
+        data.value_balance = Amount::zero();
+        data.shielded_spends.push(spend);
+        data.shielded_outputs.push(output.clone());
+        data.binding_sig = Some(Signature::read(&[0u8; 64][..]).unwrap());
+        let tx = data.freeze().unwrap();
+
+        let height = BlockHeight::from(12345);
+        let hash = BlockHash([1; 32]);
+        let prev_hash = BlockHash([2; 32]);
+        let cb = CompactBlock::from_transactions(height, hash, prev_hash, [&tx]);
+
+        assert_eq!(cb.height(), height);
+        assert_eq!(cb.hash(), hash);
+        assert_eq!(cb.prev_hash(), prev_hash);
+        assert_eq!(cb.vtx.len(), 1);
+
+        let ctx = &cb.vtx[0];
+        assert_eq!(ctx.hash, tx.txid().0.to_vec());
+        assert_eq!(ctx.spends.len(), 1);
+        assert_eq!(ctx.spends[0].nf().unwrap(), nf);
+        assert_eq!(ctx.outputs.len(), 1);
+        assert_eq!(ctx.outputs[0].cmu().unwrap(), cmu);
+        assert_eq!(ctx.outputs[0].epk().unwrap(), ephemeral_key);
+        assert_eq!(
+            ctx.outputs[0].ciphertext,
+            output.enc_ciphertext[..zcash_note_encryption::COMPACT_NOTE_SIZE]
+        );
+    }
+
+    #[test]
+    fn from_transactions_omits_transparent_only_transactions() {
+        let data = TransactionData::new();
+        let tx = data.freeze().unwrap();
+
+        let cb = CompactBlock::from_transactions(
+            BlockHeight::from(1),
+            BlockHash([0; 32]),
+            BlockHash([0; 32]),
+            [&tx],
+        );
+
+        assert!(cb.vtx.is_empty());
+    }
+}
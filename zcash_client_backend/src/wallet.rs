@@ -11,6 +11,13 @@ use zcash_primitives::{
     transaction::{components::Amount, TxId},
 };
 
+#[cfg(feature = "transparent-inputs")]
+use zcash_primitives::{
+    consensus::BlockHeight,
+    legacy::{Script, TransparentAddress},
+    transaction::components::OutPoint,
+};
+
 /// A type-safe wrapper for account identifiers.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct AccountId(pub u32);
@@ -63,15 +70,69 @@ pub struct WalletShieldedOutput<N> {
     pub nf: N,
 }
 
+/// A strategy for choosing which of an account's spendable notes to draw upon to meet
+/// a target value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NoteSelectionStrategy {
+    /// Prefer older notes first, minimizing the number of notes consumed regardless of
+    /// how much change results. This is the strategy note selection has always used.
+    MinimizeInputs,
+
+    /// Prefer smaller notes first, so the selected total lands as close as possible to
+    /// the target value from above. This minimizes the change output, which is more
+    /// private: a payment with little or no change more closely resembles one with no
+    /// leftover value to link back to the sender.
+    MinimizeChange,
+}
+
+impl Default for NoteSelectionStrategy {
+    fn default() -> Self {
+        NoteSelectionStrategy::MinimizeInputs
+    }
+}
+
 /// Information about a note that is tracked by the wallet that is available for spending,
 /// with sufficient information for use in note selection.
 pub struct SpendableNote {
     pub diversifier: Diversifier,
     pub note_value: Amount,
     pub rseed: Rseed,
+    /// Whether this note was received as change from one of the wallet's own
+    /// transactions, so a privacy-conscious caller can warn before selecting it (linking
+    /// change back to the transaction that produced it can be more identifying than
+    /// linking two independently-received notes).
+    pub is_change: bool,
     pub witness: IncrementalWitness<Node>,
 }
 
+/// A transparent output received by the wallet, tracked so that it can later be spent to
+/// shield its value.
+#[cfg(feature = "transparent-inputs")]
+pub struct WalletTransparentOutput {
+    pub address: TransparentAddress,
+    pub outpoint: OutPoint,
+    pub value: Amount,
+    pub height: BlockHeight,
+}
+
+#[cfg(feature = "transparent-inputs")]
+impl WalletTransparentOutput {
+    /// Returns the address this output was received at.
+    pub fn address(&self) -> &TransparentAddress {
+        &self.address
+    }
+
+    /// Returns the value of this output, in zatoshis.
+    pub fn value(&self) -> Amount {
+        self.value
+    }
+
+    /// Returns the output script corresponding to this output's address.
+    pub fn script(&self) -> Script {
+        self.address.script()
+    }
+}
+
 /// Describes a policy for which outgoing viewing key should be able to decrypt
 /// transaction outputs.
 ///
@@ -3,6 +3,8 @@
 use ff::PrimeField;
 use std::collections::HashSet;
 use std::convert::TryFrom;
+use std::error;
+use std::fmt;
 use subtle::{ConditionallySelectable, ConstantTimeEq, CtOption};
 use zcash_note_encryption::ShieldedOutput;
 use zcash_primitives::{
@@ -19,6 +21,120 @@ use zcash_primitives::{
 use crate::proto::compact_formats::{CompactBlock, CompactOutput};
 use crate::wallet::{AccountId, WalletShieldedOutput, WalletShieldedSpend, WalletTx};
 
+/// Errors that can occur while scanning a [`CompactBlock`].
+///
+/// [`CompactBlock`]: crate::proto::compact_formats::CompactBlock
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanError {
+    /// Two outputs within the same block had identical note commitments.
+    ///
+    /// This is cryptographically implausible for legitimate output commitments, but a
+    /// malicious or corrupted [`CompactBlock`] could still contain it; inserting both
+    /// into the commitment tree would silently corrupt it, so scanning stops instead.
+    ///
+    /// [`CompactBlock`]: crate::proto::compact_formats::CompactBlock
+    DuplicateCommitment(BlockHeight),
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScanError::DuplicateCommitment(height) => write!(
+                f,
+                "Block at height {} contains two outputs with the same note commitment",
+                height
+            ),
+        }
+    }
+}
+
+impl error::Error for ScanError {}
+
+/// A probabilistic pre-filter over a set of Sapling nullifiers, used to cheaply rule out
+/// the vast majority of compact spends that do not belong to the wallet before falling
+/// back to an exact comparison against the tracked nullifier set.
+///
+/// A negative result from [`NullifierBloomFilter::may_contain`] guarantees that the
+/// nullifier is not tracked; a positive result is only a hint, and callers MUST confirm
+/// it against the exact nullifier set (as [`scan_block_with_nullifier_filter`] does) to
+/// avoid treating a false positive as a real spend.
+///
+/// **This filter is a deliberate trade of the constant-time guarantee [`scan_block`]
+/// otherwise provides for throughput.** [`scan_block_with_nullifier_filter`] only pays
+/// for the exact, constant-time comparison against `nullifiers` when this filter reports
+/// a spend as possibly present; a negative result skips it entirely. Since a negative
+/// result is only ever returned for spends that are genuinely not the wallet's own, this
+/// makes the time to scan a spend correlate with whether it belongs to the wallet,
+/// which a network observer (for example, a lightwalletd operator) can in principle use
+/// to distinguish this wallet's spends from others'. Pass `None` instead of a filter to
+/// [`scan_block_with_nullifier_filter`] (or use [`scan_block`]) when that guarantee
+/// matters more than the throughput this filter buys.
+pub struct NullifierBloomFilter {
+    bits: Vec<u64>,
+    num_bits: usize,
+    num_hashes: u32,
+}
+
+impl NullifierBloomFilter {
+    /// Constructs an empty filter sized for approximately `expected_items` nullifiers
+    /// at a false-positive rate of roughly 1%.
+    pub fn with_capacity(expected_items: usize) -> Self {
+        let num_bits = (((expected_items.max(1) as f64) * 9.6).ceil() as usize).max(64);
+        Self {
+            bits: vec![0u64; num_bits.div_ceil(64)],
+            num_bits,
+            num_hashes: 7,
+        }
+    }
+
+    /// Constructs a filter containing exactly the given nullifiers.
+    pub fn from_nullifiers<'a>(nullifiers: impl Iterator<Item = &'a Nullifier>) -> Self {
+        let nullifiers: Vec<_> = nullifiers.collect();
+        let mut filter = Self::with_capacity(nullifiers.len());
+        for nf in nullifiers {
+            filter.insert(nf);
+        }
+        filter
+    }
+
+    // A pair of independent 64-bit FNV-1a hashes of the nullifier bytes, used as the
+    // basis for Kirsch-Mitzenmacher double hashing of the filter's `num_hashes` indices.
+    fn hash_pair(nf: &Nullifier) -> (u64, u64) {
+        const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let fnv1a = |bytes: &[u8]| {
+            bytes.iter().fold(FNV_OFFSET, |hash, &b| {
+                (hash ^ u64::from(b)).wrapping_mul(FNV_PRIME)
+            })
+        };
+
+        let bytes = nf.0;
+        (fnv1a(&bytes[..16]), fnv1a(&bytes[16..]) | 1)
+    }
+
+    fn bit_indices(&self, nf: &Nullifier) -> impl Iterator<Item = usize> {
+        let (h1, h2) = Self::hash_pair(nf);
+        let num_bits = self.num_bits;
+        (0..self.num_hashes).map(move |i| {
+            (h1.wrapping_add(h2.wrapping_mul(u64::from(i))) as usize) % num_bits
+        })
+    }
+
+    /// Inserts a nullifier into the filter.
+    pub fn insert(&mut self, nf: &Nullifier) {
+        for idx in self.bit_indices(nf).collect::<Vec<_>>() {
+            self.bits[idx / 64] |= 1 << (idx % 64);
+        }
+    }
+
+    /// Returns `false` if `nf` is definitely not in the filter, or `true` if it might be.
+    pub fn may_contain(&self, nf: &Nullifier) -> bool {
+        self.bit_indices(nf)
+            .all(|idx| self.bits[idx / 64] & (1 << (idx % 64)) != 0)
+    }
+}
+
 /// Scans a [`CompactOutput`] with a set of [`ScanningKey`]s.
 ///
 /// Returns a [`WalletShieldedOutput`] and corresponding [`IncrementalWitness`] if this
@@ -55,37 +171,50 @@ fn scan_output<P: consensus::Parameters, K: ScanningKey>(
     }
     tree.append(node).unwrap();
 
-    for (account, vk) in vks.iter() {
-        let (note, to) = match vk.try_decryption(params, height, &output) {
-            Some(ret) => ret,
-            None => continue,
-        };
+    let (account, note, to) = try_decrypt(params, height, &output, vks)?;
 
-        // A note is marked as "change" if the account that received it
-        // also spent notes in the same transaction. This will catch,
-        // for instance:
-        // - Change created by spending fractions of notes.
-        // - Notes created by consolidation transactions.
-        // - Notes sent from one account to itself.
-        let is_change = spent_from_accounts.contains(&account);
-
-        let witness = IncrementalWitness::from_tree(tree);
-        let nf = vk.nf(&note, &witness);
-
-        return Some(WalletShieldedOutput {
-            index,
-            cmu: output.cmu,
-            epk: output.epk,
-            account: **account,
-            note,
-            to,
-            is_change,
-            witness,
-            nf,
-        });
-    }
+    // A note is marked as "change" if the account that received it
+    // also spent notes in the same transaction. This will catch,
+    // for instance:
+    // - Change created by spending fractions of notes.
+    // - Notes created by consolidation transactions.
+    // - Notes sent from one account to itself.
+    let is_change = spent_from_accounts.contains(&account);
+
+    let witness = IncrementalWitness::from_tree(tree);
+    let nf = vks
+        .iter()
+        .find(|(vk_account, _)| **vk_account == account)
+        .map(|(_, vk)| vk.nf(&note, &witness))?;
+
+    Some(WalletShieldedOutput {
+        index,
+        cmu: output.cmu,
+        epk: output.epk,
+        account,
+        note,
+        to,
+        is_change,
+        witness,
+        nf,
+    })
+}
 
-    None
+/// Attempts trial decryption of a single compact output against a set of scanning keys.
+///
+/// This is the expensive, per-output part of [`scan_output`]: it performs no tree or
+/// witness mutation, so it is safe to run concurrently across outputs (see
+/// [`multicore::trial_decrypt_outputs`]).
+fn try_decrypt<P: consensus::Parameters, K: ScanningKey>(
+    params: &P,
+    height: BlockHeight,
+    output: &CompactOutputDescription,
+    vks: &[(&AccountId, &K)],
+) -> Option<(AccountId, Note, PaymentAddress)> {
+    vks.iter().find_map(|(account, vk)| {
+        vk.try_decryption(params, height, output)
+            .map(|(note, to)| (**account, note, to))
+    })
 }
 
 /// A key that can be used to perform trial decryption and nullifier
@@ -172,6 +301,19 @@ impl ScanningKey for SaplingIvk {
 /// The given [`CommitmentTree`] and existing [`IncrementalWitness`]es are
 /// incremented appropriately.
 ///
+/// This only trial-decrypts Sapling outputs. [`CompactBlock`]'s [`CompactTx`] also
+/// carries a `CompactOrchardAction` for each Orchard action; **those are not decrypted,
+/// and any Orchard receipts to a wallet using this function will be silently missed.**
+/// Decrypting them needs the `orchard` crate -- for Orchard note decryption, nullifier
+/// derivation, and the Orchard note commitment tree -- which this workspace does not
+/// currently depend on, plus an Orchard counterpart to this function, [`ScanningKey`],
+/// [`WalletTx`], and the pool-aware `ShieldedOutput` plumbing they would need to feed.
+/// None of that is implemented yet; the wire format's `actions` field has been added
+/// ahead of it so that follow-up work won't also require a protocol-compatibility bump,
+/// but the scan itself remains Sapling-only until that follow-up lands.
+///
+/// [`CompactTx`]: crate::proto::compact_formats::CompactTx
+///
 /// The implementation of [`ScanningKey`] may either support or omit the computation of
 /// the nullifiers for received notes; the implementation for [`ExtendedFullViewingKey`]
 /// will derive the nullifiers for received notes and return them as part of the resulting
@@ -193,10 +335,54 @@ pub fn scan_block<P: consensus::Parameters, K: ScanningKey>(
     nullifiers: &[(AccountId, Nullifier)],
     tree: &mut CommitmentTree<Node>,
     existing_witnesses: &mut [&mut IncrementalWitness<Node>],
-) -> Vec<WalletTx<K::Nf>> {
+) -> Result<Vec<WalletTx<K::Nf>>, ScanError> {
+    scan_block_with_nullifier_filter(
+        params,
+        block,
+        vks,
+        nullifiers,
+        None,
+        tree,
+        existing_witnesses,
+    )
+}
+
+/// Equivalent to [`scan_block`], but accepts an optional [`NullifierBloomFilter`] that is
+/// consulted before the exact nullifier comparison. A spend whose nullifier the filter
+/// reports as definitely absent skips the exact comparison entirely; a spend the filter
+/// reports as possibly present still goes through the same constant-time comparison
+/// against `nullifiers` as [`scan_block`], so false positives from the filter can never
+/// cause an incorrect spend to be recorded.
+///
+/// Passing `Some` filter here trades away the constant-time guarantee [`scan_block`]
+/// otherwise provides for the whole batch of spends: see [`NullifierBloomFilter`]'s
+/// documentation for why. Pass `None` (or call [`scan_block`]) to keep that guarantee.
+///
+/// Returns [`ScanError::DuplicateCommitment`] without applying any changes to `tree` or
+/// `existing_witnesses` if `block` contains two outputs with the same note commitment.
+pub fn scan_block_with_nullifier_filter<P: consensus::Parameters, K: ScanningKey>(
+    params: &P,
+    block: CompactBlock,
+    vks: &[(&AccountId, &K)],
+    nullifiers: &[(AccountId, Nullifier)],
+    nullifier_filter: Option<&NullifierBloomFilter>,
+    tree: &mut CommitmentTree<Node>,
+    existing_witnesses: &mut [&mut IncrementalWitness<Node>],
+) -> Result<Vec<WalletTx<K::Nf>>, ScanError> {
     let mut wtxs: Vec<WalletTx<K::Nf>> = vec![];
     let block_height = block.height();
 
+    // Reject a block containing two outputs with the same note commitment before
+    // touching the commitment tree; inserting both would silently corrupt it.
+    let mut seen_cmus: HashSet<&[u8]> = HashSet::new();
+    for tx in &block.vtx {
+        for output in &tx.outputs {
+            if !seen_cmus.insert(&output.cmu) {
+                return Err(ScanError::DuplicateCommitment(block_height));
+            }
+        }
+    }
+
     for tx in block.vtx.into_iter() {
         let num_spends = tx.spends.len();
         let num_outputs = tx.outputs.len();
@@ -211,20 +397,35 @@ pub fn scan_block<P: consensus::Parameters, K: ScanningKey>(
                 let spend_nf = spend.nf().expect(
                     "Could not deserialize nullifier for spend from protobuf representation.",
                 );
-                // Find the first tracked nullifier that matches this spend, and produce
-                // a WalletShieldedSpend if there is a match, in constant time.
-                nullifiers
-                    .iter()
-                    .map(|&(account, nf)| CtOption::new(account, nf.ct_eq(&spend_nf)))
-                    .fold(
-                        CtOption::new(AccountId::default(), 0.into()),
-                        |first, next| CtOption::conditional_select(&next, &first, first.is_some()),
-                    )
-                    .map(|account| WalletShieldedSpend {
-                        index,
-                        nf: spend_nf,
-                        account,
-                    })
+                // If a Bloom filter was supplied, rule out the common case of a spend
+                // that clearly isn't ours before paying for the exact comparison below.
+                // A positive (possibly-present) result always falls through to the same
+                // constant-time scan used when no filter is present, so a false positive
+                // can never be mistaken for a real spend. This branch is NOT constant
+                // time with respect to the filter's result, by design: see
+                // `NullifierBloomFilter`'s documentation for the timing tradeoff this
+                // makes when `nullifier_filter` is `Some`.
+                let account = if nullifier_filter.is_none_or(|f| f.may_contain(&spend_nf)) {
+                    // Find the first tracked nullifier that matches this spend, and produce
+                    // a WalletShieldedSpend if there is a match, in constant time.
+                    nullifiers
+                        .iter()
+                        .map(|&(account, nf)| CtOption::new(account, nf.ct_eq(&spend_nf)))
+                        .fold(
+                            CtOption::new(AccountId::default(), 0.into()),
+                            |first, next| {
+                                CtOption::conditional_select(&next, &first, first.is_some())
+                            },
+                        )
+                } else {
+                    CtOption::new(AccountId::default(), 0.into())
+                };
+
+                account.map(|account| WalletShieldedSpend {
+                    index,
+                    nf: spend_nf,
+                    account,
+                })
             })
             .filter(|spend| spend.is_some().into())
             .map(|spend| spend.unwrap())
@@ -288,7 +489,67 @@ pub fn scan_block<P: consensus::Parameters, K: ScanningKey>(
         }
     }
 
-    wtxs
+    Ok(wtxs)
+}
+
+/// A thread-pool-backed variant of the trial-decryption step of [`scan_block`].
+///
+/// Trial decryption dominates the cost of scanning, and each output can be decrypted
+/// independently of the others, so it parallelizes well; by contrast, appending to the
+/// commitment tree and deriving witnesses must happen in the original output order, so
+/// those steps remain serial. Callers should trial-decrypt a transaction's outputs with
+/// [`trial_decrypt_outputs`] and then fold the results into the tree in index order, the
+/// same way [`scan_output`] does internally.
+#[cfg(feature = "multicore")]
+pub mod multicore {
+    use std::convert::TryFrom;
+
+    use rayon::prelude::*;
+    use zcash_primitives::{
+        consensus::{self, BlockHeight},
+        sapling::{Note, PaymentAddress},
+        transaction::components::sapling::CompactOutputDescription,
+    };
+
+    use super::{try_decrypt, AccountId, ScanningKey};
+    use crate::proto::compact_formats::CompactOutput;
+
+    /// Trial-decrypts a batch of [`CompactOutput`]s against the given scanning keys,
+    /// returning one result per output in its original index order.
+    ///
+    /// Set `num_threads` to cap the size of the thread pool used for this call (for
+    /// example, `Some(1)` to keep mobile builds single-threaded); `None` uses rayon's
+    /// global pool.
+    ///
+    /// [`CompactOutput`]: crate::proto::compact_formats::CompactOutput
+    pub fn trial_decrypt_outputs<P, K>(
+        params: &P,
+        height: BlockHeight,
+        outputs: &[CompactOutput],
+        vks: &[(&AccountId, &K)],
+        num_threads: Option<usize>,
+    ) -> Vec<Option<(AccountId, Note, PaymentAddress)>>
+    where
+        P: consensus::Parameters + Sync,
+        K: ScanningKey + Sync,
+    {
+        let decrypt_one = |output: &CompactOutput| {
+            let output = CompactOutputDescription::try_from(output.clone()).ok()?;
+            try_decrypt(params, height, &output, vks)
+        };
+
+        let run = || outputs.par_iter().map(decrypt_one).collect();
+
+        match num_threads {
+            Some(n) if n <= 1 => outputs.iter().map(decrypt_one).collect(),
+            Some(n) => rayon::ThreadPoolBuilder::new()
+                .num_threads(n)
+                .build()
+                .expect("failed to build trial decryption thread pool")
+                .install(run),
+            None => run(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -302,17 +563,38 @@ mod tests {
         memo::MemoBytes,
         merkle_tree::CommitmentTree,
         sapling::{
-            note_encryption::sapling_note_encryption, util::generate_random_rseed, Note, Nullifier,
-            SaplingIvk,
+            note_encryption::sapling_note_encryption, util::generate_random_rseed, Node, Note,
+            Nullifier, SaplingIvk,
         },
         transaction::components::Amount,
         zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
     };
 
-    use super::scan_block;
+    use super::{scan_block, ScanError};
     use crate::proto::compact_formats::{CompactBlock, CompactOutput, CompactSpend, CompactTx};
     use crate::wallet::AccountId;
 
+    /// Builds a [`CompactOutput`] for someone else's note, with a random note commitment
+    /// and no decryptable ciphertext for the account under test.
+    fn random_compact_output(mut rng: impl RngCore) -> CompactOutput {
+        let fake_cmu = {
+            let fake_cmu = bls12_381::Scalar::random(&mut rng);
+            fake_cmu.to_repr().as_ref().to_owned()
+        };
+        let fake_epk = {
+            let mut buffer = [0; 64];
+            rng.fill_bytes(&mut buffer);
+            let fake_esk = jubjub::Fr::from_bytes_wide(&buffer);
+            let fake_epk = SPENDING_KEY_GENERATOR * fake_esk;
+            fake_epk.to_bytes().to_vec()
+        };
+        let mut cout = CompactOutput::new();
+        cout.set_cmu(fake_cmu);
+        cout.set_epk(fake_epk);
+        cout.set_ciphertext(vec![0; 52]);
+        cout
+    }
+
     fn random_compact_tx(mut rng: impl RngCore) -> CompactTx {
         let fake_nf = {
             let mut nf = vec![0; 32];
@@ -435,7 +717,7 @@ mod tests {
             &[],
             &mut tree,
             &mut [],
-        );
+        ).unwrap();
         assert_eq!(txs.len(), 1);
 
         let tx = &txs[0];
@@ -452,6 +734,120 @@ mod tests {
         assert_eq!(tx.shielded_outputs[0].witness.root(), tree.root());
     }
 
+    #[test]
+    fn scan_block_rejects_duplicate_commitment() {
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+
+        let mut cb = fake_compact_block(
+            1u32.into(),
+            Nullifier([0; 32]),
+            extfvk.clone(),
+            Amount::from_u64(5).unwrap(),
+            false,
+        );
+
+        // Duplicate the cmu of our tx's only output into a second output of the same
+        // block, simulating a malformed block with a repeated note commitment.
+        let cmu = cb.vtx[1].outputs[0].cmu.clone();
+        let mut dup = CompactOutput::new();
+        dup.set_cmu(cmu);
+        dup.set_epk(vec![0; 32]);
+        dup.set_ciphertext(vec![0; 52]);
+        cb.vtx[1].outputs.push(dup);
+
+        let mut tree = CommitmentTree::empty();
+        match scan_block(
+            &Network::TestNetwork,
+            cb,
+            &[(&AccountId(0), &extfvk)],
+            &[],
+            &mut tree,
+            &mut [],
+        ) {
+            Err(ScanError::DuplicateCommitment(height)) => assert_eq!(height, 1u32.into()),
+            other => panic!(
+                "Expected DuplicateCommitment, got {:?}",
+                other.map(|txs| txs.len())
+            ),
+        }
+    }
+
+    #[test]
+    fn scan_block_appends_foreign_cmus_interleaved_with_our_output() {
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        let to = extfvk.default_address().unwrap().1;
+
+        let mut rng = OsRng;
+        let rseed = generate_random_rseed(&Network::TestNetwork, 1u32.into(), &mut rng);
+        let note = Note {
+            g_d: to.diversifier().g_d().unwrap(),
+            pk_d: *to.pk_d(),
+            value: Amount::from_u64(7).unwrap().into(),
+            rseed,
+        };
+        let encryptor = sapling_note_encryption::<_, Network>(
+            Some(extfvk.fvk.ovk),
+            note.clone(),
+            to,
+            MemoBytes::empty(),
+            &mut rng,
+        );
+        let mut our_output = CompactOutput::new();
+        our_output.set_cmu(note.cmu().to_repr().as_ref().to_owned());
+        our_output.set_epk(encryptor.epk().to_bytes().to_vec());
+        our_output.set_ciphertext(encryptor.encrypt_note_plaintext().as_ref()[..52].to_vec());
+
+        // A single transaction with several outputs we don't own interleaved around the
+        // one output that is ours.
+        let mut ctx = CompactTx::new();
+        let mut txid = vec![0; 32];
+        rng.fill_bytes(&mut txid);
+        ctx.set_hash(txid);
+        ctx.outputs.push(random_compact_output(&mut rng));
+        ctx.outputs.push(random_compact_output(&mut rng));
+        ctx.outputs.push(our_output);
+        ctx.outputs.push(random_compact_output(&mut rng));
+
+        let mut cb = CompactBlock::new();
+        cb.set_height(1u64);
+        cb.vtx.push(ctx);
+
+        // Independently build the tree that scanning should produce, by appending every
+        // output's commitment (foreign and ours alike) in order.
+        let mut independent_tree = CommitmentTree::empty();
+        for output in &cb.vtx[0].outputs {
+            let mut repr = [0; 32];
+            repr.copy_from_slice(&output.cmu);
+            independent_tree.append(Node::new(repr)).unwrap();
+        }
+
+        let mut tree = CommitmentTree::empty();
+        let txs = scan_block(
+            &Network::TestNetwork,
+            cb,
+            &[(&AccountId(0), &extfvk)],
+            &[],
+            &mut tree,
+            &mut [],
+        )
+        .unwrap();
+        assert_eq!(txs.len(), 1);
+
+        let tx = &txs[0];
+        assert_eq!(tx.shielded_outputs.len(), 1);
+        let our_scanned_output = &tx.shielded_outputs[0];
+        // Our output was the third of four, so its witness position is 2.
+        assert_eq!(our_scanned_output.index, 2);
+        assert_eq!(our_scanned_output.witness.position(), 2);
+
+        // The foreign commitments must have been folded into the tree alongside ours,
+        // rather than dropped, or the roots below would not match.
+        assert_eq!(tree.root(), independent_tree.root());
+        assert_eq!(our_scanned_output.witness.root(), independent_tree.root());
+    }
+
     #[test]
     fn scan_block_with_txs_after_my_tx() {
         let extsk = ExtendedSpendingKey::master(&[]);
@@ -474,7 +870,7 @@ mod tests {
             &[],
             &mut tree,
             &mut [],
-        );
+        ).unwrap();
         assert_eq!(txs.len(), 1);
 
         let tx = &txs[0];
@@ -510,7 +906,7 @@ mod tests {
             &[(account, nf)],
             &mut tree,
             &mut [],
-        );
+        ).unwrap();
         assert_eq!(txs.len(), 1);
 
         let tx = &txs[0];
@@ -523,4 +919,110 @@ mod tests {
         assert_eq!(tx.shielded_spends[0].nf, nf);
         assert_eq!(tx.shielded_spends[0].account, account);
     }
+
+    #[test]
+    fn scan_block_with_bloom_filter_matches_exact_scan() {
+        use super::{scan_block_with_nullifier_filter, NullifierBloomFilter};
+
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        let nf = Nullifier([7; 32]);
+        let account = AccountId(12);
+
+        let cb = fake_compact_block(1u32.into(), nf, extfvk, Amount::from_u64(5).unwrap(), false);
+        let vks: Vec<(&AccountId, &SaplingIvk)> = vec![];
+        let tracked_nullifiers = [(account, nf)];
+
+        let exact_txs = {
+            let mut tree = CommitmentTree::empty();
+            scan_block(
+                &Network::TestNetwork,
+                cb.clone(),
+                &vks[..],
+                &tracked_nullifiers,
+                &mut tree,
+                &mut [],
+            )
+            .unwrap()
+        };
+
+        let filter = NullifierBloomFilter::from_nullifiers(
+            tracked_nullifiers.iter().map(|(_, nf)| nf),
+        );
+        let filtered_txs = {
+            let mut tree = CommitmentTree::empty();
+            scan_block_with_nullifier_filter(
+                &Network::TestNetwork,
+                cb,
+                &vks[..],
+                &tracked_nullifiers,
+                Some(&filter),
+                &mut tree,
+                &mut [],
+            )
+            .unwrap()
+        };
+
+        assert_eq!(exact_txs.len(), 1);
+        assert_eq!(filtered_txs.len(), exact_txs.len());
+        assert_eq!(filtered_txs[0].shielded_spends.len(), 1);
+        assert_eq!(
+            filtered_txs[0].shielded_spends.len(),
+            exact_txs[0].shielded_spends.len()
+        );
+        assert_eq!(filtered_txs[0].shielded_spends[0].index, 0);
+        assert_eq!(filtered_txs[0].shielded_spends[0].nf, nf);
+        assert_eq!(filtered_txs[0].shielded_spends[0].account, account);
+
+        // The inserted nullifier must always be reported as possibly present; a false
+        // negative here would cause scan_block_with_nullifier_filter to miss a real spend.
+        assert!(filter.may_contain(&nf));
+    }
+
+    #[cfg(feature = "multicore")]
+    #[test]
+    fn trial_decrypt_outputs_matches_serial_scan() {
+        use super::multicore::trial_decrypt_outputs;
+
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+
+        let cb = fake_compact_block(
+            1u32.into(),
+            Nullifier([0; 32]),
+            extfvk.clone(),
+            Amount::from_u64(5).unwrap(),
+            true,
+        );
+        let vks: Vec<(&AccountId, &ExtendedFullViewingKey)> = vec![(&AccountId(0), &extfvk)];
+
+        // The parallel path should find the same single matching output as the serial
+        // scan, regardless of the requested thread count.
+        for num_threads in [None, Some(1), Some(4)] {
+            let outputs = &cb.vtx[1].outputs;
+            let results = trial_decrypt_outputs(
+                &Network::TestNetwork,
+                1u32.into(),
+                outputs,
+                &vks[..],
+                num_threads,
+            );
+            assert_eq!(results.len(), 1);
+            let (account, note, _) = results[0].clone().unwrap();
+            assert_eq!(account, AccountId(0));
+            assert_eq!(note.value, 5);
+        }
+
+        let mut tree = CommitmentTree::empty();
+        let txs = scan_block(
+            &Network::TestNetwork,
+            cb,
+            &vks[..],
+            &[],
+            &mut tree,
+            &mut [],
+        ).unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].shielded_outputs[0].note.value, 5);
+    }
 }
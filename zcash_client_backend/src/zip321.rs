@@ -14,7 +14,7 @@ use nom::{
 use zcash_primitives::{
     consensus,
     memo::{self, MemoBytes},
-    transaction::components::Amount,
+    transaction::components::{amount::MAX_MONEY, Amount},
 };
 
 #[cfg(any(test, feature = "test-dependencies"))]
@@ -22,6 +22,17 @@ use std::cmp::Ordering;
 
 use crate::address::RecipientAddress;
 
+/// The default maximum number of recipients that [`TransactionRequest::from_uri`] will
+/// accept. Use [`TransactionRequest::from_uri_with_limit`] to override this when parsing
+/// untrusted input (e.g. a pasted URI) calls for a different limit.
+pub const DEFAULT_MAX_RECIPIENTS: usize = 100;
+
+/// The maximum length, in bytes, of a URI that [`TransactionRequest::from_uri`] and
+/// [`TransactionRequest::from_uri_with_limit`] will attempt to parse. This bounds the
+/// parsing work performed on adversarial input before the recipient count itself can be
+/// checked.
+pub const MAX_URI_LENGTH: usize = 16 * 1024;
+
 /// Errors that may be produced in decoding of memos.
 #[derive(Debug)]
 pub enum MemoError {
@@ -29,6 +40,53 @@ pub enum MemoError {
     MemoBytesError(memo::Error),
 }
 
+/// Errors that may be produced in parsing a ZIP 321 URI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Zip321Error {
+    /// The URI does not conform to ZIP 321 syntax, or requests a structurally-invalid
+    /// payment (e.g. a memo attached to a transparent recipient).
+    ParseError(String),
+    /// The sum of the requested payment amounts overflows, or exceeds the maximum
+    /// possible Zcash money supply.
+    AmountOverflow,
+    /// The URI contained a `req-`-prefixed parameter that this implementation does not
+    /// recognize. Per ZIP 321, a `req-` prefix marks a parameter that a receiving wallet
+    /// MUST understand or else reject the payment request outright, so this is returned
+    /// instead of silently ignoring the parameter the way an unrecognized non-`req-`
+    /// parameter is.
+    UnsupportedRequiredParam(String),
+    /// The URI requested more recipients than the caller's configured limit, per
+    /// [`TransactionRequest::from_uri_with_limit`]. Returned instead of building the
+    /// full payment list, since a caller parsing pasted or otherwise untrusted input
+    /// wants to reject an oversized request rather than pay the cost of constructing
+    /// it.
+    TooManyRecipients { count: usize, max: usize },
+}
+
+impl std::fmt::Display for Zip321Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Zip321Error::ParseError(s) => write!(f, "{}", s),
+            Zip321Error::AmountOverflow => write!(
+                f,
+                "Sum of payment amounts in ZIP 321 request exceeds the maximum money supply"
+            ),
+            Zip321Error::UnsupportedRequiredParam(name) => write!(
+                f,
+                "Required parameter {} is not recognized by this implementation",
+                name
+            ),
+            Zip321Error::TooManyRecipients { count, max } => write!(
+                f,
+                "ZIP 321 request has {} recipients, exceeding the maximum of {}",
+                count, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Zip321Error {}
+
 /// Converts a [`MemoBytes`] value to a ZIP 321 compatible base64-encoded string.
 ///
 /// [`MemoBytes`]: zcash_primitives::memo::MemoBytes
@@ -110,6 +168,11 @@ pub struct TransactionRequest {
 }
 
 impl TransactionRequest {
+    /// Returns the individual payments that make up this request.
+    pub fn payments(&self) -> &[Payment] {
+        &self.payments
+    }
+
     /// A utility for use in tests to help check round-trip serialization properties.
     #[cfg(any(test, feature = "test-dependencies"))]
     pub(in crate::zip321) fn normalize<P: consensus::Parameters>(&mut self, params: &P) {
@@ -201,18 +264,43 @@ impl TransactionRequest {
         }
     }
 
-    /// Parse the provided URI to a payment request value.
-    pub fn from_uri<P: consensus::Parameters>(params: &P, uri: &str) -> Result<Self, String> {
+    /// Parse the provided URI to a payment request value, rejecting requests for more
+    /// than [`DEFAULT_MAX_RECIPIENTS`] recipients.
+    ///
+    /// Use [`TransactionRequest::from_uri_with_limit`] to parse untrusted input (e.g. a
+    /// pasted URI) against a different recipient limit.
+    pub fn from_uri<P: consensus::Parameters>(params: &P, uri: &str) -> Result<Self, Zip321Error> {
+        Self::from_uri_with_limit(params, uri, DEFAULT_MAX_RECIPIENTS)
+    }
+
+    /// Parse the provided URI to a payment request value, rejecting requests for more
+    /// than `max_recipients` recipients with [`Zip321Error::TooManyRecipients`].
+    ///
+    /// The URI itself is also rejected, prior to any other parsing, if it exceeds
+    /// [`MAX_URI_LENGTH`] bytes.
+    pub fn from_uri_with_limit<P: consensus::Parameters>(
+        params: &P,
+        uri: &str,
+        max_recipients: usize,
+    ) -> Result<Self, Zip321Error> {
+        if uri.len() > MAX_URI_LENGTH {
+            return Err(Zip321Error::ParseError(format!(
+                "URI length of {} bytes exceeds the maximum of {} bytes.",
+                uri.len(),
+                MAX_URI_LENGTH
+            )));
+        }
+
         // Parse the leading zcash:<address>
         let (rest, primary_addr_param) =
-            parse::lead_addr(params)(uri).map_err(|e| e.to_string())?;
+            parse::lead_addr(params)(uri).map_err(|e| Zip321Error::ParseError(e.to_string()))?;
 
         // Parse the remaining parameters as an undifferentiated list
         let (_, xs) = all_consuming(preceded(
             char('?'),
             separated_list0(char('&'), parse::zcashparam(params)),
         ))(rest)
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| Zip321Error::ParseError(e.to_string()))?;
 
         // Construct sets of payment parameters, keyed by the payment index.
         let mut params_by_index: HashMap<usize, Vec<parse::Param>> = HashMap::new();
@@ -231,10 +319,10 @@ impl TransactionRequest {
 
                 Some(current) => {
                     if parse::has_duplicate_param(&current, &p.param) {
-                        return Err(format!(
+                        return Err(Zip321Error::ParseError(format!(
                             "Found duplicate parameter {:?} at index {}",
                             p.param, p.payment_index
-                        ));
+                        )));
                     } else {
                         current.push(p.param);
                     }
@@ -242,12 +330,47 @@ impl TransactionRequest {
             }
         }
 
+        // Reject an oversized recipient count before doing the work of building the
+        // full payment list, so a caller parsing untrusted input pays a bounded cost.
+        if params_by_index.len() > max_recipients {
+            return Err(Zip321Error::TooManyRecipients {
+                count: params_by_index.len(),
+                max: max_recipients,
+            });
+        }
+
         // Build the actual payment values from the index.
-        params_by_index
+        let payments = params_by_index
             .into_iter()
             .map(|(i, params)| parse::to_payment(params, i))
             .collect::<Result<Vec<_>, _>>()
-            .map(|payments| TransactionRequest { payments })
+            .map_err(Zip321Error::ParseError)?;
+
+        // Per ZIP 321, any parameter whose name begins with `req-` must be understood by
+        // the receiving wallet or else the request must be rejected outright. This
+        // implementation does not currently recognize any `req-` parameters, so any that
+        // appear here are by definition unsupported.
+        for payment in &payments {
+            if let Some((name, _)) = payment
+                .other_params
+                .iter()
+                .find(|(name, _)| name.starts_with("req-"))
+            {
+                return Err(Zip321Error::UnsupportedRequiredParam(name.clone()));
+            }
+        }
+
+        // Sum the requested amounts with overflow detection, so that a crafted URI
+        // requesting amounts that individually fit within `Amount`'s range but overflow
+        // or exceed the money supply cap in aggregate is rejected here rather than
+        // panicking deeper in a caller's note selection.
+        payments
+            .iter()
+            .try_fold(0i64, |acc, p| acc.checked_add(i64::from(p.amount)))
+            .filter(|&total| total <= MAX_MONEY)
+            .ok_or(Zip321Error::AmountOverflow)?;
+
+        Ok(TransactionRequest { payments })
     }
 }
 
@@ -589,10 +712,6 @@ mod parse {
                 .map(Param::Memo)
                 .map_err(|e| format!("Decoded memo was invalid: {:?}", e)),
 
-            other if other.starts_with("req-") => {
-                Err(format!("Required parameter {} not recognized", other))
-            }
-
             other => percent_decode(value.as_bytes())
                 .decode_utf8()
                 .map(|s| Param::Other(other.to_string(), s.into_owned()))
@@ -621,7 +740,7 @@ pub mod testing {
     use zcash_primitives::{
         consensus::TEST_NETWORK, legacy::testing::arb_transparent_addr,
         sapling::keys::testing::arb_shielded_addr,
-        transaction::components::amount::testing::arb_nonnegative_amount,
+        transaction::components::amount::{testing::arb_nonnegative_amount, MAX_MONEY},
     };
 
     use crate::address::RecipientAddress;
@@ -671,7 +790,17 @@ pub mod testing {
     }
 
     prop_compose! {
-        pub fn arb_zip321_request()(payments in vec(arb_zip321_payment(), 1..10)) -> TransactionRequest {
+        pub fn arb_zip321_request()(
+            payments in vec(arb_zip321_payment(), 1..10).prop_filter(
+                "sum of payment amounts must not exceed MAX_MONEY, or TransactionRequest::from_uri would reject it",
+                |payments| {
+                    payments
+                        .iter()
+                        .try_fold(0i64, |acc, p| acc.checked_add(i64::from(p.amount)))
+                        .map_or(false, |total| total <= MAX_MONEY)
+                },
+            )
+        ) -> TransactionRequest {
             let mut req = TransactionRequest { payments };
             req.normalize(&TEST_NETWORK); // just to make test comparisons easier
             req
@@ -706,7 +835,7 @@ mod tests {
         memo_from_base64, memo_to_base64,
         parse::{parse_amount, zcashparam, Param},
         render::amount_str,
-        MemoBytes, Payment, TransactionRequest,
+        MemoBytes, Payment, TransactionRequest, Zip321Error,
     };
     use crate::encoding::decode_payment_address;
 
@@ -912,6 +1041,76 @@ mod tests {
         assert!(i10r.is_err());
     }
 
+    #[test]
+    fn from_uri_rejects_total_amount_exceeding_max_money() {
+        // Each individual amount is within `Amount`'s valid range on its own, but their
+        // sum exceeds `MAX_MONEY`, which used to reach note selection unchecked.
+        let uri = "zcash:?address=tmEZhbWHTpdKMw5it8YDspUXSMGQyFwovpU&amount=20000000&address.1=ztestsapling10yy2ex5dcqkclhc7z7yrnjq2z6feyjad56ptwlfgmy77dmaqqrl9gyhprdx59qgmsnyfska2kez&amount.1=2000000";
+        match TransactionRequest::from_uri(&TEST_NETWORK, &uri) {
+            Err(Zip321Error::AmountOverflow) => (),
+            other => panic!("Expected AmountOverflow, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_uri_rejects_unrecognized_req_param() {
+        let uri = "zcash:tmEZhbWHTpdKMw5it8YDspUXSMGQyFwovpU?amount=1&req-future-feature=1";
+        match TransactionRequest::from_uri(&TEST_NETWORK, &uri) {
+            Err(Zip321Error::UnsupportedRequiredParam(name)) => {
+                assert_eq!(name, "req-future-feature")
+            }
+            other => panic!("Expected UnsupportedRequiredParam, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_uri_ignores_unrecognized_non_req_param() {
+        let uri = "zcash:tmEZhbWHTpdKMw5it8YDspUXSMGQyFwovpU?amount=1&futuristic-param=1";
+        let r = TransactionRequest::from_uri(&TEST_NETWORK, &uri).unwrap();
+        assert_eq!(
+            r.payments.get(0).map(|p| p.other_params.clone()),
+            Some(vec![("futuristic-param".to_string(), "1".to_string())])
+        );
+    }
+
+    #[test]
+    fn from_uri_with_limit_rejects_too_many_recipients() {
+        let uri = "zcash:?address=tmEZhbWHTpdKMw5it8YDspUXSMGQyFwovpU&amount=1&address.1=tmEZhbWHTpdKMw5it8YDspUXSMGQyFwovpU&amount.1=1&address.2=tmEZhbWHTpdKMw5it8YDspUXSMGQyFwovpU&amount.2=1";
+        match TransactionRequest::from_uri_with_limit(&TEST_NETWORK, &uri, 2) {
+            Err(Zip321Error::TooManyRecipients { count: 3, max: 2 }) => (),
+            other => panic!("Expected TooManyRecipients, got {:?}", other),
+        }
+
+        // The same request is accepted once the limit permits it.
+        assert!(TransactionRequest::from_uri_with_limit(&TEST_NETWORK, &uri, 3).is_ok());
+    }
+
+    #[test]
+    fn from_uri_rejects_uri_exceeding_max_length() {
+        let padding = "a".repeat(super::MAX_URI_LENGTH);
+        let uri = format!(
+            "zcash:tmEZhbWHTpdKMw5it8YDspUXSMGQyFwovpU?amount=1&message={}",
+            padding
+        );
+        assert!(uri.len() > super::MAX_URI_LENGTH);
+
+        match TransactionRequest::from_uri(&TEST_NETWORK, &uri) {
+            Err(Zip321Error::ParseError(_)) => (),
+            other => panic!("Expected ParseError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_uri_rejects_invalid_percent_encoded_utf8_without_panicking() {
+        // `%ff%fe` is not valid UTF-8, but is made up of characters `qchars` accepts, so
+        // it reaches percent-decoding rather than being rejected by the URI grammar.
+        let uri = "zcash:tmEZhbWHTpdKMw5it8YDspUXSMGQyFwovpU?amount=1&message=%ff%fe";
+        match TransactionRequest::from_uri(&TEST_NETWORK, &uri) {
+            Err(Zip321Error::ParseError(_)) => (),
+            other => panic!("Expected ParseError, got {:?}", other),
+        }
+    }
+
     #[cfg(all(test, feature = "test-dependencies"))]
     proptest! {
         #[test]
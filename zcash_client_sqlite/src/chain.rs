@@ -70,25 +70,31 @@ mod tests {
 
     use zcash_primitives::{
         block::BlockHash,
+        merkle_tree::CommitmentTree,
         transaction::components::Amount,
         zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
     };
 
     use zcash_client_backend::data_api::WalletRead;
     use zcash_client_backend::data_api::{
-        chain::{scan_cached_blocks, validate_chain},
+        chain::{
+            scan_cached_blocks, scan_cached_blocks_with_cancellation,
+            scan_cached_blocks_with_notify, validate_chain,
+        },
         error::{ChainInvalid, Error},
+        PrunedBlock, WalletWrite,
     };
+    use zcash_client_backend::welding_rig::scan_block;
 
     use crate::{
         chain::init::init_cache_database,
         error::SqliteClientError,
         tests::{
-            self, fake_compact_block, fake_compact_block_spending, insert_into_cache,
-            sapling_activation_height,
+            self, fake_compact_block, fake_compact_block_spending,
+            fake_compact_block_two_accounts, insert_into_cache, sapling_activation_height,
         },
         wallet::{
-            get_balance,
+            get_balance, get_received_note_count, get_witnesses,
             init::{init_accounts_table, init_wallet_db},
             rewind_to_height,
         },
@@ -379,6 +385,108 @@ mod tests {
         assert_eq!(get_balance(&db_data, AccountId(0)).unwrap(), value + value2);
     }
 
+    #[test]
+    fn chain_tip_tracks_latest_scanned_block() {
+        let cache_file = NamedTempFile::new().unwrap();
+        let db_cache = BlockDb::for_path(cache_file.path()).unwrap();
+        init_cache_database(&db_cache).unwrap();
+
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        // Add an account to the wallet
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        init_accounts_table(&db_data, &[extfvk.clone()]).unwrap();
+
+        // No blocks scanned yet, so there is no cached tip.
+        assert_eq!((&db_data).get_max_height_hash().unwrap(), None);
+
+        // Create and scan two fake CompactBlocks.
+        let (cb, _) = fake_compact_block(
+            sapling_activation_height(),
+            BlockHash([0; 32]),
+            extfvk.clone(),
+            Amount::from_u64(5).unwrap(),
+        );
+        let (cb2, _) = fake_compact_block(
+            sapling_activation_height() + 1,
+            cb.hash(),
+            extfvk,
+            Amount::from_u64(7).unwrap(),
+        );
+        insert_into_cache(&db_cache, &cb);
+        insert_into_cache(&db_cache, &cb2);
+
+        let mut db_write = db_data.get_update_ops().unwrap();
+        scan_cached_blocks(&tests::network(), &db_cache, &mut db_write, None).unwrap();
+
+        // The O(1) cached tip should match the height and hash of the most recently
+        // scanned block.
+        assert_eq!(
+            (&db_data).get_max_height_hash().unwrap(),
+            Some((sapling_activation_height() + 1, cb2.hash()))
+        );
+
+        // Rewinding should bring the cached tip back in line with the new chain tip.
+        rewind_to_height(&db_data, sapling_activation_height()).unwrap();
+        assert_eq!(
+            (&db_data).get_max_height_hash().unwrap(),
+            Some((sapling_activation_height(), cb.hash()))
+        );
+    }
+
+    #[test]
+    fn get_tip_block_time_reports_latest_block_time() {
+        let cache_file = NamedTempFile::new().unwrap();
+        let db_cache = BlockDb::for_path(cache_file.path()).unwrap();
+        init_cache_database(&db_cache).unwrap();
+
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        init_accounts_table(&db_data, &[extfvk.clone()]).unwrap();
+
+        // No blocks scanned yet, so there is no tip block time.
+        assert_eq!((&db_data).get_tip_block_time().unwrap(), None);
+
+        let (mut cb, _) = fake_compact_block(
+            sapling_activation_height(),
+            BlockHash([0; 32]),
+            extfvk.clone(),
+            Amount::from_u64(5).unwrap(),
+        );
+        cb.set_time(1_600_000_000);
+        insert_into_cache(&db_cache, &cb);
+
+        let mut db_write = db_data.get_update_ops().unwrap();
+        scan_cached_blocks(&tests::network(), &db_cache, &mut db_write, None).unwrap();
+        assert_eq!(
+            (&db_data).get_tip_block_time().unwrap(),
+            Some(1_600_000_000)
+        );
+
+        let (mut cb2, _) = fake_compact_block(
+            sapling_activation_height() + 1,
+            cb.hash(),
+            extfvk,
+            Amount::from_u64(7).unwrap(),
+        );
+        cb2.set_time(1_600_000_100);
+        insert_into_cache(&db_cache, &cb2);
+        scan_cached_blocks(&tests::network(), &db_cache, &mut db_write, None).unwrap();
+
+        // The tip block time follows the most recently scanned block, not the first.
+        assert_eq!(
+            (&db_data).get_tip_block_time().unwrap(),
+            Some(1_600_000_100)
+        );
+    }
+
     #[test]
     fn scan_cached_blocks_requires_sequential_blocks() {
         let cache_file = NamedTempFile::new().unwrap();
@@ -488,6 +596,353 @@ mod tests {
         assert_eq!(get_balance(&db_data, AccountId(0)).unwrap(), value + value2);
     }
 
+    #[test]
+    fn advance_by_block_attributes_witnesses_to_the_correct_account() {
+        let cache_file = NamedTempFile::new().unwrap();
+        let db_cache = BlockDb::for_path(cache_file.path()).unwrap();
+        init_cache_database(&db_cache).unwrap();
+
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        let extfvk1 = ExtendedFullViewingKey::from(&ExtendedSpendingKey::master(&[]));
+        let extfvk2 = ExtendedFullViewingKey::from(&ExtendedSpendingKey::master(&[0]));
+        init_accounts_table(&db_data, &[extfvk1.clone(), extfvk2.clone()]).unwrap();
+
+        // Both accounts receive a note in the same block.
+        let value1 = Amount::from_u64(5).unwrap();
+        let value2 = Amount::from_u64(7).unwrap();
+        let (cb, _) = fake_compact_block_two_accounts(
+            sapling_activation_height(),
+            BlockHash([0; 32]),
+            &[(extfvk1, value1), (extfvk2, value2)],
+        );
+        insert_into_cache(&db_cache, &cb);
+
+        let mut db_write = db_data.get_update_ops().unwrap();
+        scan_cached_blocks(&tests::network(), &db_cache, &mut db_write, None).unwrap();
+
+        // Each account's balance reflects only its own note...
+        assert_eq!(get_balance(&db_data, AccountId(0)).unwrap(), value1);
+        assert_eq!(get_balance(&db_data, AccountId(1)).unwrap(), value2);
+
+        // ...and the witness stored for each note is associated with the note's own
+        // account, not mixed up between the two notes received in this block.
+        let witnesses = get_witnesses(&db_data, sapling_activation_height()).unwrap();
+        assert_eq!(witnesses.len(), 2);
+        for (note_id, _witness) in witnesses {
+            let NoteId::ReceivedNoteId(id_note) = note_id else {
+                panic!("expected a received note id");
+            };
+            let (account, value): (u32, i64) = db_data
+                .conn
+                .query_row(
+                    "SELECT account, value FROM received_notes WHERE id_note = ?",
+                    [id_note],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            let expected_value = if account == 0 { value1 } else { value2 };
+            assert_eq!(Amount::from_i64(value).unwrap(), expected_value);
+        }
+    }
+
+    #[test]
+    fn scan_cached_blocks_with_notify_reports_outputs_before_the_write() {
+        let cache_file = NamedTempFile::new().unwrap();
+        let db_cache = BlockDb::for_path(cache_file.path()).unwrap();
+        init_cache_database(&db_cache).unwrap();
+
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        init_accounts_table(&db_data, &[extfvk.clone()]).unwrap();
+
+        let value = Amount::from_u64(5).unwrap();
+        let (cb, _) = fake_compact_block(
+            sapling_activation_height(),
+            BlockHash([0; 32]),
+            extfvk,
+            value,
+        );
+        insert_into_cache(&db_cache, &cb);
+
+        let mut db_write = db_data.get_update_ops().unwrap();
+        let mut notified_values = vec![];
+        scan_cached_blocks_with_notify(&tests::network(), &db_cache, &mut db_write, None, |output| {
+            notified_values.push(output.note.value);
+        })
+        .unwrap();
+
+        // The callback fires for the note as it's scanned, independent of (and prior to)
+        // the wallet balance being persisted.
+        assert_eq!(notified_values, vec![5]);
+        assert_eq!(get_balance(&db_data, AccountId(0)).unwrap(), value);
+    }
+
+    #[test]
+    fn scan_cached_blocks_with_cancellation_stops_and_resumes_cleanly() {
+        let cache_file = NamedTempFile::new().unwrap();
+        let db_cache = BlockDb::for_path(cache_file.path()).unwrap();
+        init_cache_database(&db_cache).unwrap();
+
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        init_accounts_table(&db_data, &[extfvk.clone()]).unwrap();
+
+        let value1 = Amount::from_u64(5).unwrap();
+        let (cb1, _) = fake_compact_block(
+            sapling_activation_height(),
+            BlockHash([0; 32]),
+            extfvk.clone(),
+            value1,
+        );
+        insert_into_cache(&db_cache, &cb1);
+
+        let value2 = Amount::from_u64(7).unwrap();
+        let (cb2, _) =
+            fake_compact_block(sapling_activation_height() + 1, cb1.hash(), extfvk, value2);
+        insert_into_cache(&db_cache, &cb2);
+
+        // Simulate the app having already been backgrounded by the time scanning starts.
+        let cancelled = std::sync::atomic::AtomicBool::new(true);
+        let mut db_write = db_data.get_update_ops().unwrap();
+        match scan_cached_blocks_with_cancellation(
+            &tests::network(),
+            &db_cache,
+            &mut db_write,
+            None,
+            &cancelled,
+        ) {
+            Err(SqliteClientError::BackendError(Error::Canceled)) => (),
+            other => panic!("expected a cancellation error, got {:?}", other),
+        }
+
+        // Nothing was scanned, so the wallet remains at its initial state.
+        assert_eq!(get_balance(&db_data, AccountId(0)).unwrap(), Amount::zero());
+
+        // A subsequent, uncancelled call resumes from scratch and completes the scan.
+        scan_cached_blocks(&tests::network(), &db_cache, &mut db_write, None).unwrap();
+        assert_eq!(
+            get_balance(&db_data, AccountId(0)).unwrap(),
+            value1 + value2
+        );
+    }
+
+    #[test]
+    fn scan_cached_blocks_tracks_received_note_count() {
+        let cache_file = NamedTempFile::new().unwrap();
+        let db_cache = BlockDb::for_path(cache_file.path()).unwrap();
+        init_cache_database(&db_cache).unwrap();
+
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        // Add an account to the wallet
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        init_accounts_table(&db_data, &[extfvk.clone()]).unwrap();
+
+        // No blocks scanned yet, so no notes have been counted
+        assert_eq!(get_received_note_count(&db_data).unwrap(), 0);
+
+        // Create a fake CompactBlock sending value to the address
+        let value = Amount::from_u64(5).unwrap();
+        let (cb, _) = fake_compact_block(
+            sapling_activation_height(),
+            BlockHash([0; 32]),
+            extfvk.clone(),
+            value,
+        );
+        insert_into_cache(&db_cache, &cb);
+
+        // Create a second fake CompactBlock sending more value to the address
+        let value2 = Amount::from_u64(7).unwrap();
+        let (cb2, _) =
+            fake_compact_block(sapling_activation_height() + 1, cb.hash(), extfvk, value2);
+        insert_into_cache(&db_cache, &cb2);
+
+        // Scan the cache
+        let mut db_write = db_data.get_update_ops().unwrap();
+        scan_cached_blocks(&tests::network(), &db_cache, &mut db_write, None).unwrap();
+
+        // Each fake block contributes exactly one shielded output, so the aggregated
+        // count should match the total number of notes received across both blocks.
+        assert_eq!(get_received_note_count(&db_data).unwrap(), 2);
+    }
+
+    #[test]
+    fn scan_cached_blocks_reports_a_scan_summary() {
+        let cache_file = NamedTempFile::new().unwrap();
+        let db_cache = BlockDb::for_path(cache_file.path()).unwrap();
+        init_cache_database(&db_cache).unwrap();
+
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        init_accounts_table(&db_data, &[extfvk.clone()]).unwrap();
+
+        let value = Amount::from_u64(5).unwrap();
+        let (cb, _) = fake_compact_block(
+            sapling_activation_height(),
+            BlockHash([0; 32]),
+            extfvk.clone(),
+            value,
+        );
+        insert_into_cache(&db_cache, &cb);
+
+        let value2 = Amount::from_u64(7).unwrap();
+        let (cb2, _) =
+            fake_compact_block(sapling_activation_height() + 1, cb.hash(), extfvk, value2);
+        insert_into_cache(&db_cache, &cb2);
+
+        let mut db_write = db_data.get_update_ops().unwrap();
+        let summary =
+            scan_cached_blocks(&tests::network(), &db_cache, &mut db_write, None).unwrap();
+
+        assert_eq!(summary.blocks_scanned, 2);
+        assert_eq!(summary.txs_found, 2);
+        assert_eq!(summary.outputs_found, 2);
+        assert_eq!(summary.spends_found, 0);
+    }
+
+    #[test]
+    fn get_balance_by_confirmations_reports_one_amount_per_bucket() {
+        let cache_file = NamedTempFile::new().unwrap();
+        let db_cache = BlockDb::for_path(cache_file.path()).unwrap();
+        init_cache_database(&db_cache).unwrap();
+
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        init_accounts_table(&db_data, &[extfvk.clone()]).unwrap();
+
+        // A note mined in the first scanned block...
+        let value = Amount::from_u64(50000).unwrap();
+        let (cb, _) = fake_compact_block(
+            sapling_activation_height(),
+            BlockHash([0; 32]),
+            extfvk.clone(),
+            value,
+        );
+        insert_into_cache(&db_cache, &cb);
+
+        // ...and a second note mined one block later.
+        let (cb2, _) =
+            fake_compact_block(sapling_activation_height() + 1, cb.hash(), extfvk, value);
+        insert_into_cache(&db_cache, &cb2);
+
+        let mut db_write = db_data.get_update_ops().unwrap();
+        scan_cached_blocks(&tests::network(), &db_cache, &mut db_write, None).unwrap();
+
+        // A single confirmation is satisfied by the tip block itself, so both notes
+        // are counted; requiring two or more confirmations excludes the note mined at
+        // the tip, leaving only the older one.
+        let balances =
+            (&db_data)
+                .get_balance_by_confirmations(AccountId(0), &[1, 2, 100])
+                .unwrap();
+        assert_eq!(
+            balances,
+            vec![(1, value + value), (2, value), (100, value)]
+        );
+    }
+
+    #[test]
+    fn backfill_nullifiers_is_a_noop_when_every_note_already_has_one() {
+        let cache_file = NamedTempFile::new().unwrap();
+        let db_cache = BlockDb::for_path(cache_file.path()).unwrap();
+        init_cache_database(&db_cache).unwrap();
+
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        init_accounts_table(&db_data, &[extfvk.clone()]).unwrap();
+
+        let (cb, _) = fake_compact_block(
+            sapling_activation_height(),
+            BlockHash([0; 32]),
+            extfvk.clone(),
+            Amount::from_u64(50000).unwrap(),
+        );
+        insert_into_cache(&db_cache, &cb);
+
+        let mut db_write = db_data.get_update_ops().unwrap();
+        scan_cached_blocks(&tests::network(), &db_cache, &mut db_write, None).unwrap();
+
+        // `received_notes.nf` is `NOT NULL`, and every note scanned above already had
+        // its nullifier computed as part of scanning, so there is nothing to backfill.
+        assert_eq!(
+            db_write.backfill_nullifiers(AccountId(0), &extfvk).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn get_tree_size_reports_the_cached_leaf_count() {
+        let cache_file = NamedTempFile::new().unwrap();
+        let db_cache = BlockDb::for_path(cache_file.path()).unwrap();
+        init_cache_database(&db_cache).unwrap();
+
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        init_accounts_table(&db_data, &[extfvk.clone()]).unwrap();
+
+        // No block at this height has been scanned yet.
+        assert_eq!(
+            (&db_data).get_tree_size(sapling_activation_height()).unwrap(),
+            None
+        );
+
+        let (cb, _) = fake_compact_block(
+            sapling_activation_height(),
+            BlockHash([0; 32]),
+            extfvk.clone(),
+            Amount::from_u64(5).unwrap(),
+        );
+        let (cb2, _) =
+            fake_compact_block(sapling_activation_height() + 1, cb.hash(), extfvk, Amount::from_u64(7).unwrap());
+        insert_into_cache(&db_cache, &cb);
+        insert_into_cache(&db_cache, &cb2);
+
+        let mut db_write = db_data.get_update_ops().unwrap();
+        scan_cached_blocks(&tests::network(), &db_cache, &mut db_write, None).unwrap();
+
+        // Each block adds one note commitment, so the tree grows by one leaf per block.
+        assert_eq!(
+            (&db_data).get_tree_size(sapling_activation_height()).unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            (&db_data)
+                .get_tree_size(sapling_activation_height() + 1)
+                .unwrap(),
+            Some(2)
+        );
+    }
+
     #[test]
     fn scan_cached_blocks_finds_change_notes() {
         let cache_file = NamedTempFile::new().unwrap();
@@ -545,4 +1000,334 @@ mod tests {
         // Account balance should equal the change
         assert_eq!(get_balance(&db_data, AccountId(0)).unwrap(), value - value2);
     }
+
+    #[test]
+    fn advance_by_block_reports_added_and_removed_note_counts() {
+        let cache_file = NamedTempFile::new().unwrap();
+        let db_cache = BlockDb::for_path(cache_file.path()).unwrap();
+        init_cache_database(&db_cache).unwrap();
+
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        // Add an account to the wallet
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        init_accounts_table(&db_data, &[extfvk.clone()]).unwrap();
+
+        // Receive a note via the normal scanning path.
+        let value = Amount::from_u64(5).unwrap();
+        let (cb, nf) = fake_compact_block(
+            sapling_activation_height(),
+            BlockHash([0; 32]),
+            extfvk.clone(),
+            value,
+        );
+        insert_into_cache(&db_cache, &cb);
+
+        let mut db_write = db_data.get_update_ops().unwrap();
+        scan_cached_blocks(&tests::network(), &db_cache, &mut db_write, None).unwrap();
+
+        // Build a block that spends the note we just received, and drive
+        // `advance_by_block` directly so we can inspect the counts it reports
+        // rather than the aggregate balance.
+        let extsk2 = ExtendedSpendingKey::master(&[0]);
+        let to2 = extsk2.default_address().unwrap().1;
+        let value2 = Amount::from_u64(2).unwrap();
+        let cb2 = fake_compact_block_spending(
+            sapling_activation_height() + 1,
+            cb.hash(),
+            (nf, value),
+            extfvk.clone(),
+            to2,
+            value2,
+        );
+
+        let current_height = cb2.height();
+        let block_hash = BlockHash::from_slice(&cb2.hash);
+        let block_prev_hash = cb2.prev_hash();
+        let block_time = cb2.time;
+
+        let extfvks = [(&AccountId(0), &extfvk)];
+        let mut tree = db_write
+            .get_commitment_tree(sapling_activation_height())
+            .unwrap()
+            .unwrap();
+        let mut witnesses = db_write
+            .get_witnesses(sapling_activation_height())
+            .unwrap();
+        let nullifiers = db_write.get_nullifiers().unwrap();
+
+        let txs = {
+            let mut witness_refs: Vec<_> = witnesses.iter_mut().map(|w| &mut w.1).collect();
+            scan_block(
+                &tests::network(),
+                cb2,
+                &extfvks,
+                &nullifiers,
+                &mut tree,
+                &mut witness_refs[..],
+            )
+            .unwrap()
+        };
+
+        let (new_witnesses, counts) = db_write
+            .advance_by_block(
+                &PrunedBlock {
+                    block_height: current_height,
+                    block_hash,
+                    prev_hash: block_prev_hash,
+                    block_time,
+                    commitment_tree: &tree,
+                    transactions: &txs,
+                },
+                &witnesses,
+            )
+            .unwrap();
+
+        assert_eq!(counts.notes_removed, 1);
+        assert_eq!(counts.notes_added, new_witnesses.len());
+        assert_eq!(counts.notes_added, 1);
+    }
+
+    #[test]
+    fn advance_by_block_rejects_prev_hash_mismatch() {
+        let cache_file = NamedTempFile::new().unwrap();
+        let db_cache = BlockDb::for_path(cache_file.path()).unwrap();
+        init_cache_database(&db_cache).unwrap();
+
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        // Add an account to the wallet
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        init_accounts_table(&db_data, &[extfvk.clone()]).unwrap();
+
+        // Receive a note via the normal scanning path, establishing a chain tip.
+        let value = Amount::from_u64(5).unwrap();
+        let (cb, _) = fake_compact_block(
+            sapling_activation_height(),
+            BlockHash([0; 32]),
+            extfvk.clone(),
+            value,
+        );
+        insert_into_cache(&db_cache, &cb);
+
+        let mut db_write = db_data.get_update_ops().unwrap();
+        scan_cached_blocks(&tests::network(), &db_cache, &mut db_write, None).unwrap();
+
+        // Build a second block that claims a prev-hash other than the tip we just
+        // persisted, simulating a reorg the caller has not rewound past.
+        let (cb2, _) = fake_compact_block(
+            sapling_activation_height() + 1,
+            BlockHash([0xff; 32]),
+            extfvk.clone(),
+            Amount::from_u64(7).unwrap(),
+        );
+
+        let current_height = cb2.height();
+        let block_hash = BlockHash::from_slice(&cb2.hash);
+        let block_prev_hash = cb2.prev_hash();
+        let block_time = cb2.time;
+
+        let extfvks = [(&AccountId(0), &extfvk)];
+        let mut tree = db_write
+            .get_commitment_tree(sapling_activation_height())
+            .unwrap()
+            .unwrap();
+        let mut witnesses = db_write
+            .get_witnesses(sapling_activation_height())
+            .unwrap();
+        let nullifiers = db_write.get_nullifiers().unwrap();
+
+        let txs = {
+            let mut witness_refs: Vec<_> = witnesses.iter_mut().map(|w| &mut w.1).collect();
+            scan_block(
+                &tests::network(),
+                cb2,
+                &extfvks,
+                &nullifiers,
+                &mut tree,
+                &mut witness_refs[..],
+            )
+            .unwrap()
+        };
+
+        match db_write.advance_by_block(
+            &PrunedBlock {
+                block_height: current_height,
+                block_hash,
+                prev_hash: block_prev_hash,
+                block_time,
+                commitment_tree: &tree,
+                transactions: &txs,
+            },
+            &witnesses,
+        ) {
+            Err(SqliteClientError::BlockConflict { at_height }) => {
+                assert_eq!(at_height, current_height)
+            }
+            other => panic!("Expected BlockConflict, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn advance_by_blocks_batches_multiple_blocks_in_one_transaction() {
+        let cache_file = NamedTempFile::new().unwrap();
+        let db_cache = BlockDb::for_path(cache_file.path()).unwrap();
+        init_cache_database(&db_cache).unwrap();
+
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        // Add an account to the wallet
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        init_accounts_table(&db_data, &[extfvk.clone()]).unwrap();
+
+        // Build a block that receives a note, followed by a block that spends it, and
+        // scan both ahead of time so that we can drive `advance_by_blocks` with both
+        // in a single call.
+        let value = Amount::from_u64(5).unwrap();
+        let (cb, nf) = fake_compact_block(
+            sapling_activation_height(),
+            BlockHash([0; 32]),
+            extfvk.clone(),
+            value,
+        );
+
+        let extsk2 = ExtendedSpendingKey::master(&[0]);
+        let to2 = extsk2.default_address().unwrap().1;
+        let value2 = Amount::from_u64(2).unwrap();
+        let cb2 = fake_compact_block_spending(
+            sapling_activation_height() + 1,
+            cb.hash(),
+            (nf, value),
+            extfvk.clone(),
+            to2,
+            value2,
+        );
+
+        let extfvks = [(&AccountId(0), &extfvk)];
+        let mut db_write = db_data.get_update_ops().unwrap();
+        let mut tree = db_write
+            .get_commitment_tree(sapling_activation_height())
+            .unwrap()
+            .unwrap_or_else(CommitmentTree::empty);
+        let mut witnesses = vec![];
+
+        let height1 = cb.height();
+        let hash1 = BlockHash::from_slice(&cb.hash);
+        let prev_hash1 = cb.prev_hash();
+        let time1 = cb.time;
+        let txs1 = {
+            let mut witness_refs: Vec<_> = witnesses.iter_mut().map(|w: &mut (_, _)| &mut w.1).collect();
+            scan_block(
+                &tests::network(),
+                cb,
+                &extfvks,
+                &[],
+                &mut tree,
+                &mut witness_refs[..],
+            )
+            .unwrap()
+        };
+
+        // The note received in block1 hasn't been persisted to the database yet (that
+        // only happens once `advance_by_blocks` runs below), so supply its nullifier
+        // directly rather than via `get_nullifiers`, mirroring the state the database
+        // will be in partway through processing the batch.
+        let nullifiers = [(AccountId(0), nf)];
+        let tree1 = tree.clone();
+        let block1 = PrunedBlock {
+            block_height: height1,
+            block_hash: hash1,
+            prev_hash: prev_hash1,
+            block_time: time1,
+            commitment_tree: &tree1,
+            transactions: &txs1,
+        };
+
+        let height2 = cb2.height();
+        let hash2 = BlockHash::from_slice(&cb2.hash);
+        let prev_hash2 = cb2.prev_hash();
+        let time2 = cb2.time;
+        let txs2 = {
+            let mut witness_refs: Vec<_> = witnesses.iter_mut().map(|w: &mut (_, _)| &mut w.1).collect();
+            scan_block(
+                &tests::network(),
+                cb2,
+                &extfvks,
+                &nullifiers,
+                &mut tree,
+                &mut witness_refs[..],
+            )
+            .unwrap()
+        };
+        let block2 = PrunedBlock {
+            block_height: height2,
+            block_hash: hash2,
+            prev_hash: prev_hash2,
+            block_time: time2,
+            commitment_tree: &tree,
+            transactions: &txs2,
+        };
+
+        let (final_witnesses, counts) = db_write
+            .advance_by_blocks(&[block1, block2], &witnesses)
+            .unwrap();
+
+        assert_eq!(counts.notes_added, 2);
+        assert_eq!(counts.notes_removed, 1);
+        assert_eq!(final_witnesses.len(), 2);
+
+        // Both blocks should have been persisted.
+        assert_eq!(get_balance(&db_data, AccountId(0)).unwrap(), value - value2);
+    }
+
+    #[test]
+    fn with_blocks_respects_limit() {
+        use zcash_client_backend::data_api::BlockSource;
+
+        let cache_file = NamedTempFile::new().unwrap();
+        let db_cache = BlockDb::for_path(cache_file.path()).unwrap();
+        init_cache_database(&db_cache).unwrap();
+
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+
+        let mut prev_hash = BlockHash([0; 32]);
+        for i in 0..5 {
+            let (cb, _) = fake_compact_block(
+                sapling_activation_height() + i,
+                prev_hash,
+                extfvk.clone(),
+                Amount::from_u64(1).unwrap(),
+            );
+            prev_hash = cb.hash();
+            insert_into_cache(&db_cache, &cb);
+        }
+
+        let mut seen = 0;
+        db_cache
+            .with_blocks(sapling_activation_height() - 1, Some(3), |_| {
+                seen += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(seen, 3);
+
+        let mut seen = 0;
+        db_cache
+            .with_blocks(sapling_activation_height() - 1, None, |_| {
+                seen += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(seen, 5);
+    }
 }
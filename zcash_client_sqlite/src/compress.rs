@@ -0,0 +1,80 @@
+//! Optional compression for large stored blobs (raw transactions and incremental
+//! witness snapshots), enabled by the `compress-storage` feature.
+//!
+//! Compressed blobs are ordinary gzip streams. [`decompress`] recognizes them by
+//! gzip's own two-byte magic number, so a blob written before this feature existed, or
+//! with it disabled, is read back unchanged rather than misinterpreted as compressed.
+
+use std::io;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Compresses `data` if the `compress-storage` feature is enabled; otherwise returns it
+/// unchanged.
+#[cfg(feature = "compress-storage")]
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(data)
+        .expect("writing to an in-memory buffer cannot fail");
+    encoder
+        .finish()
+        .expect("writing to an in-memory buffer cannot fail")
+}
+
+#[cfg(not(feature = "compress-storage"))]
+pub(crate) fn compress(data: &[u8]) -> Vec<u8> {
+    data.to_vec()
+}
+
+/// Decompresses `data` if it starts with the gzip magic number, as produced by
+/// [`compress`]; otherwise returns it unchanged, so rows written before this feature
+/// existed, or with it disabled, are still read correctly.
+pub(crate) fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    if !data.starts_with(&GZIP_MAGIC) {
+        return Ok(data.to_vec());
+    }
+
+    #[cfg(feature = "compress-storage")]
+    {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let mut out = Vec::new();
+        GzDecoder::new(data).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[cfg(not(feature = "compress-storage"))]
+    {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Row is gzip-compressed, but this build was compiled without the \
+             `compress-storage` feature required to read it.",
+        ))
+    }
+}
+
+#[cfg(all(test, feature = "compress-storage"))]
+mod tests {
+    use super::{compress, decompress};
+
+    #[test]
+    fn compress_round_trips() {
+        let data = b"a witness blob with some repeated repeated repeated bytes".to_vec();
+        let compressed = compress(&data);
+        assert_ne!(compressed, data);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn decompress_passes_through_legacy_uncompressed_data() {
+        // A blob written before this feature existed has no gzip magic number, and
+        // must be returned unchanged rather than mistaken for a compressed row.
+        let legacy = vec![0u8, 1, 2, 3, 4];
+        assert_eq!(decompress(&legacy).unwrap(), legacy);
+    }
+}
@@ -3,7 +3,10 @@
 use std::error;
 use std::fmt;
 
+use zcash_primitives::consensus::BlockHeight;
+
 use zcash_client_backend::data_api;
+use zcash_client_backend::encoding::AddressError;
 
 use crate::NoteId;
 
@@ -23,12 +26,57 @@ pub enum SqliteClientError {
     /// sent note, not a received note.
     InvalidNoteId,
 
+    /// A note's value, or its output index, is outside the range that can be
+    /// represented in the data DB's storage columns.
+    NoteValueOutOfRange,
+
+    /// An amount computed while recording wallet data (for example, a change value)
+    /// fell outside the range representable by [`Amount`], either because it was
+    /// negative or because it overflowed.
+    ///
+    /// [`Amount`]: zcash_primitives::transaction::components::Amount
+    InvalidAmount,
+
+    /// The `output_index` recorded for a sent note does not refer to an output that
+    /// actually exists, of the expected kind (Sapling or transparent), within the
+    /// transaction being stored.
+    InvalidOutputIndex,
+
+    /// A note that was selected for spending has no stored incremental witness at the
+    /// anchor height it was selected against (for example, because the witness was
+    /// pruned before the note was spent). The caller should trigger a rescan from at
+    /// or before this height to regenerate it.
+    WitnessMissing { note: NoteId, height: BlockHeight },
+
     /// Illegal attempt to reinitialize an already-initialized wallet database.
     TableNotEmpty,
 
+    /// A block passed to `advance_by_block` does not chain from the wallet's current tip:
+    /// its `prev_hash` does not match the hash of the block previously stored at
+    /// `at_height - 1`. This indicates either a reorg that the caller has not rewound
+    /// past, or an attempt to insert blocks out of order.
+    BlockConflict { at_height: BlockHeight },
+
+    /// A rewind was requested to a height further back than the wallet's configured
+    /// `max_reorg_depth`. Witnesses that far back have already been pruned, so the
+    /// rewind cannot be serviced; the caller should discard the wallet's scanned data
+    /// and perform a full rescan instead.
+    ReorgTooDeep { requested: BlockHeight, max: u32 },
+
+    /// A block-pruning request was made for a height that falls within the wallet's
+    /// retained reorg window. Blocks in this window must be kept, as a subsequent
+    /// rewind within the configured `max_reorg_depth` may still need them.
+    PruneWindowTooShallow {
+        requested: BlockHeight,
+        min_retained: BlockHeight,
+    },
+
     /// Bech32 decoding error
     Bech32(bech32::Error),
 
+    /// A Bech32-encoded address could not be decoded.
+    InvalidAddress(AddressError),
+
     /// Base58 decoding error
     Base58(bs58::decode::Error),
 
@@ -50,6 +98,7 @@ impl error::Error for SqliteClientError {
         match &self {
             SqliteClientError::InvalidMemo(e) => Some(e),
             SqliteClientError::Bech32(e) => Some(e),
+            SqliteClientError::InvalidAddress(e) => Some(e),
             SqliteClientError::DbError(e) => Some(e),
             SqliteClientError::Io(e) => Some(e),
             _ => None,
@@ -66,9 +115,17 @@ impl fmt::Display for SqliteClientError {
             SqliteClientError::IncorrectHrpExtFvk => write!(f, "Incorrect HRP for extfvk"),
             SqliteClientError::InvalidNote => write!(f, "Invalid note"),
             SqliteClientError::InvalidNoteId => write!(f, "The note ID associated with an inserted witness must correspond to a received note."),
+            SqliteClientError::NoteValueOutOfRange => write!(f, "Note value or output index is out of range."),
+            SqliteClientError::InvalidAmount => write!(f, "Computed amount is negative or out of range."),
+            SqliteClientError::InvalidOutputIndex => write!(f, "The output index does not refer to an output of the expected kind in the transaction."),
+            SqliteClientError::WitnessMissing { note, height } => write!(f, "Missing incremental witness for note {:?} at height {}", note, height),
             SqliteClientError::Bech32(e) => write!(f, "{}", e),
+            SqliteClientError::InvalidAddress(e) => write!(f, "{}", e),
             SqliteClientError::Base58(e) => write!(f, "{}", e),
             SqliteClientError::TableNotEmpty => write!(f, "Table is not empty"),
+            SqliteClientError::BlockConflict { at_height } => write!(f, "Block at height {} does not chain from the wallet's current tip", at_height),
+            SqliteClientError::ReorgTooDeep { requested, max } => write!(f, "Requested rewind to height {} exceeds the maximum reorg depth of {} blocks; a full rescan is required", requested, max),
+            SqliteClientError::PruneWindowTooShallow { requested, min_retained } => write!(f, "Requested to prune blocks below height {}, but blocks are retained down to height {} to service a rewind within the configured max reorg depth", requested, min_retained),
             SqliteClientError::DbError(e) => write!(f, "{}", e),
             SqliteClientError::Io(e) => write!(f, "{}", e),
             SqliteClientError::InvalidMemo(e) => write!(f, "{}", e),
@@ -95,6 +152,12 @@ impl From<bech32::Error> for SqliteClientError {
     }
 }
 
+impl From<AddressError> for SqliteClientError {
+    fn from(e: AddressError) -> Self {
+        SqliteClientError::InvalidAddress(e)
+    }
+}
+
 impl From<bs58::decode::Error> for SqliteClientError {
     fn from(e: bs58::decode::Error) -> Self {
         SqliteClientError::Base58(e)
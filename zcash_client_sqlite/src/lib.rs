@@ -32,10 +32,13 @@
 // Catch documentation errors caused by code changes.
 #![deny(broken_intra_doc_links)]
 
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt;
 use std::path::Path;
+use std::time::Duration;
 
+use lru::LruCache;
 use rusqlite::{Connection, Statement};
 
 use zcash_primitives::{
@@ -44,31 +47,42 @@ use zcash_primitives::{
     memo::Memo,
     merkle_tree::{CommitmentTree, IncrementalWitness},
     sapling::{Node, Nullifier, PaymentAddress},
-    transaction::{components::Amount, TxId},
+    transaction::{components::Amount, Transaction, TxId},
     zip32::ExtendedFullViewingKey,
 };
 
 use zcash_client_backend::{
+    address::RecipientAddress,
     data_api::{
-        BlockSource, PrunedBlock, ReceivedTransaction, SentTransaction, WalletRead, WalletWrite,
+        BlockAdvanceCounts, BlockSource, IntegrityWarning, PrunedBlock, ReceivedTransaction,
+        SentTransaction, WalletRead, WalletTransaction, WalletWrite,
     },
     encoding::encode_payment_address,
+    keys::UnifiedFullViewingKey,
     proto::compact_formats::CompactBlock,
-    wallet::{AccountId, SpendableNote},
+    wallet::{AccountId, NoteSelectionStrategy, SpendableNote},
 };
 
+#[cfg(feature = "transparent-inputs")]
+use zcash_client_backend::wallet::WalletTransparentOutput;
+#[cfg(feature = "transparent-inputs")]
+use zcash_primitives::legacy::TransparentAddress;
+
 use crate::error::SqliteClientError;
 
 pub mod chain;
+mod compress;
 pub mod error;
 pub mod wallet;
 
 /// A newtype wrapper for sqlite primary key values for the notes
 /// table.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum NoteId {
     SentNoteId(i64),
     ReceivedNoteId(i64),
+    #[cfg(feature = "transparent-inputs")]
+    TransparentUtxoId(i64),
 }
 
 impl fmt::Display for NoteId {
@@ -76,14 +90,102 @@ impl fmt::Display for NoteId {
         match self {
             NoteId::SentNoteId(id) => write!(f, "Sent Note {}", id),
             NoteId::ReceivedNoteId(id) => write!(f, "Received Note {}", id),
+            #[cfg(feature = "transparent-inputs")]
+            NoteId::TransparentUtxoId(id) => write!(f, "Transparent UTXO {}", id),
         }
     }
 }
 
+impl NoteId {
+    /// Encodes this note id as a short, round-trippable string suitable for passing
+    /// across an FFI boundary, unlike this type's [`Display`](fmt::Display) impl (which
+    /// is meant for logging, not parsing).
+    pub fn to_stable_string(&self) -> String {
+        match self {
+            NoteId::SentNoteId(id) => format!("s:{}", id),
+            NoteId::ReceivedNoteId(id) => format!("r:{}", id),
+            #[cfg(feature = "transparent-inputs")]
+            NoteId::TransparentUtxoId(id) => format!("t:{}", id),
+        }
+    }
+
+    /// Parses a note id previously encoded by [`NoteId::to_stable_string`].
+    pub fn from_stable_string(s: &str) -> Result<NoteId, ParseNoteIdError> {
+        let (kind, id) = s
+            .split_once(':')
+            .ok_or_else(|| ParseNoteIdError(s.to_owned()))?;
+        let id: i64 = id.parse().map_err(|_| ParseNoteIdError(s.to_owned()))?;
+
+        match kind {
+            "s" => Ok(NoteId::SentNoteId(id)),
+            "r" => Ok(NoteId::ReceivedNoteId(id)),
+            #[cfg(feature = "transparent-inputs")]
+            "t" => Ok(NoteId::TransparentUtxoId(id)),
+            _ => Err(ParseNoteIdError(s.to_owned())),
+        }
+    }
+}
+
+/// The error type returned by [`NoteId::from_stable_string`] when given a string that
+/// was not produced by [`NoteId::to_stable_string`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseNoteIdError(String);
+
+impl fmt::Display for ParseNoteIdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' is not a value produced by NoteId::to_stable_string", self.0)
+    }
+}
+
+impl std::error::Error for ParseNoteIdError {}
+
+/// The default maximum number of blocks a reorg is assumed to be able to roll back, used
+/// by [`WalletDb::for_path`]. Witnesses more than this many blocks below the chain tip
+/// are pruned, so a rewind requested beyond this depth cannot be serviced; the caller
+/// must instead perform a full rescan.
+pub const DEFAULT_MAX_REORG_DEPTH: u32 = 100;
+
+/// The default number of decoded [`Memo`] values kept in a [`WalletDb`]'s in-memory
+/// cache, used by [`WalletDb::for_path`] and [`WalletDb::for_path_with_max_reorg_depth`].
+pub const DEFAULT_MEMO_CACHE_SIZE: usize = 100;
+
+/// The default SQLite busy timeout, in milliseconds, used by [`WalletDb::for_path`] and
+/// the other [`WalletDb`] constructors that don't take an explicit timeout. This is how
+/// long a connection will wait for a lock held by another connection (e.g. a concurrent
+/// reader against the same WAL-mode database file) before giving up with
+/// `SQLITE_BUSY`, rather than failing immediately.
+pub const DEFAULT_BUSY_TIMEOUT_MILLIS: u32 = 5_000;
+
 /// A wrapper for the SQLite connection to the wallet database.
+///
+/// This crate does not itself provide an async or connection-pooled wrapper around
+/// `WalletDb`; there is no `WalletDbAsync` type here to redesign. A caller that needs to
+/// serve concurrent `WalletRead` queries without contending on a single connection can
+/// open one `WalletDb` per reader thread against the same underlying file and continue
+/// to funnel writes through a single `WalletDb`/`DataConnStmtCache`. Every `WalletDb`
+/// constructor opens its connection in WAL journal mode with a busy timeout (see
+/// [`WalletDb::for_path_with_opts`]), so this works without readers and the writer
+/// contending on SQLite's default rollback-journal lock.
+///
+/// `WalletDb` keeps a small in-memory LRU cache of decoded [`Memo`] values, keyed by
+/// `NoteId`, so that repeatedly calling [`WalletRead::get_memo`] for the same note (as a
+/// UI polling for chat-style memos might) does not re-read and re-parse the same blob
+/// each time. The cache is invalidated whenever the notes it might refer to could
+/// change: on [`WalletWrite::advance_by_block`], on
+/// [`WalletWrite::rewind_to_height`], and on [`WalletWrite::reset_sync_state`] (since
+/// `id_note` values are plain SQLite rowids and can be reused once their rows are
+/// deleted).
+///
+/// [`Memo`]: zcash_primitives::memo::Memo
+/// [`WalletRead::get_memo`]: zcash_client_backend::data_api::WalletRead::get_memo
+/// [`WalletWrite::advance_by_block`]: zcash_client_backend::data_api::WalletWrite::advance_by_block
+/// [`WalletWrite::rewind_to_height`]: zcash_client_backend::data_api::WalletWrite::rewind_to_height
+/// [`WalletWrite::reset_sync_state`]: zcash_client_backend::data_api::WalletWrite::reset_sync_state
 pub struct WalletDb<P> {
     conn: Connection,
     params: P,
+    max_reorg_depth: u32,
+    memo_cache: RefCell<LruCache<NoteId, Memo>>,
 }
 
 impl<P: consensus::Parameters> WalletDb<P> {
@@ -91,9 +193,82 @@ impl<P: consensus::Parameters> WalletDb<P> {
         &self.conn
     }
 
-    /// Construct a connection to the wallet database stored at the specified path.
+    /// Construct a connection to the wallet database stored at the specified path, using
+    /// [`DEFAULT_MAX_REORG_DEPTH`] as the maximum assumed reorg depth and
+    /// [`DEFAULT_MEMO_CACHE_SIZE`] as the size of the in-memory memo cache.
     pub fn for_path<F: AsRef<Path>>(path: F, params: P) -> Result<Self, rusqlite::Error> {
-        Connection::open(path).map(move |conn| WalletDb { conn, params })
+        Self::for_path_with_max_reorg_depth(path, params, DEFAULT_MAX_REORG_DEPTH)
+    }
+
+    /// Construct a connection to the wallet database stored at the specified path,
+    /// pruning witnesses and rejecting rewind requests beyond `max_reorg_depth` blocks
+    /// back from the chain tip, using [`DEFAULT_MEMO_CACHE_SIZE`] as the size of the
+    /// in-memory memo cache.
+    pub fn for_path_with_max_reorg_depth<F: AsRef<Path>>(
+        path: F,
+        params: P,
+        max_reorg_depth: u32,
+    ) -> Result<Self, rusqlite::Error> {
+        Self::for_path_with_memo_cache_size(
+            path,
+            params,
+            max_reorg_depth,
+            DEFAULT_MEMO_CACHE_SIZE,
+        )
+    }
+
+    /// Construct a connection to the wallet database stored at the specified path,
+    /// pruning witnesses and rejecting rewind requests beyond `max_reorg_depth` blocks
+    /// back from the chain tip, and caching up to `memo_cache_size` decoded memos, using
+    /// [`DEFAULT_BUSY_TIMEOUT_MILLIS`] as the busy timeout.
+    pub fn for_path_with_memo_cache_size<F: AsRef<Path>>(
+        path: F,
+        params: P,
+        max_reorg_depth: u32,
+        memo_cache_size: usize,
+    ) -> Result<Self, rusqlite::Error> {
+        Self::for_path_with_opts(
+            path,
+            params,
+            max_reorg_depth,
+            memo_cache_size,
+            DEFAULT_BUSY_TIMEOUT_MILLIS,
+        )
+    }
+
+    /// Construct a connection to the wallet database stored at the specified path,
+    /// pruning witnesses and rejecting rewind requests beyond `max_reorg_depth` blocks
+    /// back from the chain tip, caching up to `memo_cache_size` decoded memos, and
+    /// waiting up to `busy_timeout_millis` for a lock held by another connection before
+    /// giving up with `SQLITE_BUSY`.
+    ///
+    /// This also switches the database to WAL journal mode and enables foreign key
+    /// enforcement, so that multiple connections opened against the same file (for
+    /// example, one per reader thread, as described on [`WalletDb`]) can read
+    /// concurrently with the writer instead of contending on a single lock.
+    pub fn for_path_with_opts<F: AsRef<Path>>(
+        path: F,
+        params: P,
+        max_reorg_depth: u32,
+        memo_cache_size: usize,
+        busy_timeout_millis: u32,
+    ) -> Result<Self, rusqlite::Error> {
+        // Open by reference and keep `path` alive for the rest of this function: if `F` is
+        // an owned temporary file (e.g. `tempfile::NamedTempFile`, as used in this crate's
+        // own doc examples), moving it into `Connection::open` would drop and unlink it
+        // before the WAL pragma below gets a chance to create the `-wal`/`-shm` sidecars
+        // next to it.
+        let conn = Connection::open(path.as_ref())?;
+        conn.busy_timeout(Duration::from_millis(busy_timeout_millis as u64))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+
+        Ok(WalletDb {
+            conn,
+            params,
+            max_reorg_depth,
+            memo_cache: RefCell::new(LruCache::new(memo_cache_size)),
+        })
     }
 
     /// Given a wallet database connection, obtain a handle for the write operations
@@ -104,8 +279,13 @@ impl<P: consensus::Parameters> WalletDb<P> {
             DataConnStmtCache {
                 wallet_db: self,
                 stmt_insert_block: self.conn.prepare(
-                    "INSERT INTO blocks (height, hash, time, sapling_tree)
-                    VALUES (?, ?, ?, ?)",
+                    "INSERT INTO blocks (height, hash, time, sapling_tree, received_note_count, tree_size)
+                    VALUES (?, ?, ?, ?, ?, ?)",
+                )?,
+                stmt_update_chain_tip: self.conn.prepare(
+                    "INSERT INTO chain_tip (singleton, height, hash)
+                    VALUES (0, ?, ?)
+                    ON CONFLICT (singleton) DO UPDATE SET height = excluded.height, hash = excluded.hash",
                 )?,
                 stmt_insert_tx_meta: self.conn.prepare(
                     "INSERT INTO transactions (txid, block, tx_index)
@@ -116,16 +296,20 @@ impl<P: consensus::Parameters> WalletDb<P> {
                     SET block = ?, tx_index = ? WHERE txid = ?",
                 )?,
                 stmt_insert_tx_data: self.conn.prepare(
-                    "INSERT INTO transactions (txid, created, expiry_height, raw)
-                    VALUES (?, ?, ?, ?)",
+                    "INSERT INTO transactions (txid, created, expiry_height, raw, proposal_id)
+                    VALUES (?, ?, ?, ?, ?)",
                 )?,
                 stmt_update_tx_data: self.conn.prepare(
                     "UPDATE transactions
-                    SET expiry_height = ?, raw = ? WHERE txid = ?",
+                    SET expiry_height = ?, raw = ?, proposal_id = IFNULL(?, proposal_id)
+                    WHERE txid = ?",
                 )?,
                 stmt_select_tx_ref: self.conn.prepare(
                     "SELECT id_tx FROM transactions WHERE txid = ?",
                 )?,
+                stmt_mark_tx_replaced: self.conn.prepare(
+                    "UPDATE transactions SET replaced_by = ? WHERE id_tx = ?",
+                )?,
                 stmt_mark_recived_note_spent: self.conn.prepare(
                     "UPDATE received_notes SET spent = ? WHERE nf = ?"
                 )?,
@@ -153,8 +337,8 @@ impl<P: consensus::Parameters> WalletDb<P> {
                     WHERE tx = ? AND output_index = ?",
                 )?,
                 stmt_insert_sent_note: self.conn.prepare(
-                    "INSERT INTO sent_notes (tx, output_index, from_account, address, value, memo)
-                    VALUES (?, ?, ?, ?, ?, ?)",
+                    "INSERT INTO sent_notes (tx, output_index, from_account, address, value, memo, output_pool)
+                    VALUES (?, ?, ?, ?, ?, ?, ?)",
                 )?,
                 stmt_insert_witness: self.conn.prepare(
                     "INSERT INTO sapling_witnesses (note, block, witness)
@@ -172,6 +356,34 @@ impl<P: consensus::Parameters> WalletDb<P> {
             }
         )
     }
+
+    /// Runs `f` with a view of the database held for its whole duration inside a single
+    /// SQLite read transaction, so that a caller composing several [`WalletRead`] calls
+    /// sees one consistent snapshot even if a concurrent writer (another connection, or
+    /// another process, sharing this database file) commits in between them.
+    ///
+    /// `f` receives a shared reference, which does not by itself prevent writes:
+    /// [`WalletDb::get_update_ops`] also takes `&self`.
+    ///
+    /// [`WalletRead`]: zcash_client_backend::data_api::WalletRead
+    pub fn with_read_snapshot<F, A>(&self, f: F) -> Result<A, SqliteClientError>
+    where
+        F: FnOnce(&Self) -> Result<A, SqliteClientError>,
+    {
+        self.conn.execute("BEGIN DEFERRED", [])?;
+        match f(self) {
+            Ok(result) => {
+                self.conn.execute("COMMIT", [])?;
+                Ok(result)
+            }
+            Err(error) => {
+                // Nothing was written under a read snapshot, so there's nothing to roll
+                // back beyond ending the transaction and releasing it.
+                self.conn.execute("ROLLBACK", [])?;
+                Err(error)
+            }
+        }
+    }
 }
 
 impl<P: consensus::Parameters> WalletRead for WalletDb<P> {
@@ -183,24 +395,78 @@ impl<P: consensus::Parameters> WalletRead for WalletDb<P> {
         wallet::block_height_extrema(self).map_err(SqliteClientError::from)
     }
 
+    fn get_wallet_birthday(&self) -> Result<Option<BlockHeight>, Self::Error> {
+        wallet::get_wallet_birthday(self)
+    }
+
+    fn get_max_scanned_height(&self) -> Result<Option<BlockHeight>, Self::Error> {
+        wallet::get_max_scanned_height(self).map_err(SqliteClientError::from)
+    }
+
     fn get_block_hash(&self, block_height: BlockHeight) -> Result<Option<BlockHash>, Self::Error> {
         wallet::get_block_hash(self, block_height).map_err(SqliteClientError::from)
     }
 
+    fn get_max_height_hash(&self) -> Result<Option<(BlockHeight, BlockHash)>, Self::Error> {
+        wallet::get_max_height_hash(self).map_err(SqliteClientError::from)
+    }
+
+    fn get_tip_block_time(&self) -> Result<Option<u32>, Self::Error> {
+        wallet::get_tip_block_time(self)
+    }
+
+    fn estimate_block_time(&self, height: BlockHeight) -> Result<Option<u32>, Self::Error> {
+        wallet::estimate_block_time(self, height)
+    }
+
     fn get_tx_height(&self, txid: TxId) -> Result<Option<BlockHeight>, Self::Error> {
         wallet::get_tx_height(self, txid).map_err(SqliteClientError::from)
     }
 
+    fn get_transaction(&self, txid: TxId) -> Result<Option<Transaction>, Self::Error> {
+        wallet::get_transaction(self, txid)
+    }
+
+    fn get_sent_tx_proposal_id(&self, txid: TxId) -> Result<Option<String>, Self::Error> {
+        wallet::get_proposal_id(self, txid)
+    }
+
+    fn get_transactions(
+        &self,
+        limit: usize,
+        offset: usize,
+        tip_height: Option<BlockHeight>,
+    ) -> Result<Vec<WalletTransaction>, Self::Error> {
+        wallet::get_transactions(self, limit, offset, tip_height)
+    }
+
     fn get_extended_full_viewing_keys(
         &self,
     ) -> Result<HashMap<AccountId, ExtendedFullViewingKey>, Self::Error> {
         wallet::get_extended_full_viewing_keys(self)
     }
 
+    fn get_unified_full_viewing_keys(
+        &self,
+    ) -> Result<HashMap<AccountId, UnifiedFullViewingKey>, Self::Error> {
+        wallet::get_unified_full_viewing_keys(self)
+    }
+
+    fn check_integrity(&self) -> Result<Vec<IntegrityWarning<Self::NoteRef>>, Self::Error> {
+        wallet::check_integrity(self)
+    }
+
     fn get_address(&self, account: AccountId) -> Result<Option<PaymentAddress>, Self::Error> {
         wallet::get_address(self, account)
     }
 
+    fn get_current_address(
+        &self,
+        account: AccountId,
+    ) -> Result<Option<PaymentAddress>, Self::Error> {
+        wallet::get_current_address(self, account)
+    }
+
     fn is_valid_account_extfvk(
         &self,
         account: AccountId,
@@ -217,13 +483,88 @@ impl<P: consensus::Parameters> WalletRead for WalletDb<P> {
         wallet::get_balance_at(self, account, anchor_height)
     }
 
+    fn get_balances_at(
+        &self,
+        anchor_height: BlockHeight,
+    ) -> Result<HashMap<AccountId, Amount>, Self::Error> {
+        wallet::get_balances_at(self, anchor_height)
+    }
+
+    fn get_sent_recipients(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<(RecipientAddress, Amount)>, Self::Error> {
+        let own_addresses: Vec<RecipientAddress> = vec![
+            self.get_address(account)?.map(RecipientAddress::from),
+            self.get_change_address(account)?.map(RecipientAddress::from),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        wallet::get_sent_recipients(self, account, &own_addresses)
+    }
+
     fn get_memo(&self, id_note: Self::NoteRef) -> Result<Memo, Self::Error> {
-        match id_note {
+        if let Some(memo) = self.memo_cache.borrow_mut().get(&id_note) {
+            return Ok(memo.clone());
+        }
+
+        let memo = match id_note {
             NoteId::SentNoteId(id_note) => wallet::get_sent_memo(self, id_note),
             NoteId::ReceivedNoteId(id_note) => wallet::get_received_memo(self, id_note),
+            #[cfg(feature = "transparent-inputs")]
+            NoteId::TransparentUtxoId(_) => Err(SqliteClientError::InvalidNoteId),
+        }?;
+
+        self.memo_cache.borrow_mut().put(id_note, memo.clone());
+        Ok(memo)
+    }
+
+    fn note_has_memo(&self, id_note: Self::NoteRef) -> Result<bool, Self::Error> {
+        match id_note {
+            NoteId::SentNoteId(id_note) => wallet::sent_note_has_memo(self, id_note),
+            NoteId::ReceivedNoteId(id_note) => wallet::received_note_has_memo(self, id_note),
+            #[cfg(feature = "transparent-inputs")]
+            NoteId::TransparentUtxoId(_) => Ok(false),
         }
     }
 
+    fn get_sent_memo_for(
+        &self,
+        txid: TxId,
+        output_index: usize,
+    ) -> Result<Option<Memo>, Self::Error> {
+        wallet::get_sent_memo_for(self, txid, output_index)
+    }
+
+    fn get_memo_conversations(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<(RecipientAddress, Vec<Memo>)>, Self::Error> {
+        wallet::get_memo_conversations(self, account)
+    }
+
+    fn get_spending_tx(&self, note: Self::NoteRef) -> Result<Option<Self::TxRef>, Self::Error> {
+        match note {
+            NoteId::ReceivedNoteId(id_note) => wallet::get_received_note_spending_tx(self, id_note),
+            NoteId::SentNoteId(_) => Err(SqliteClientError::InvalidNoteId),
+            #[cfg(feature = "transparent-inputs")]
+            NoteId::TransparentUtxoId(_) => Err(SqliteClientError::InvalidNoteId),
+        }
+    }
+
+    fn get_tx_label(&self, txid: TxId) -> Result<Option<String>, Self::Error> {
+        wallet::get_tx_label(self, txid)
+    }
+
+    fn get_received_notes_for_tx(
+        &self,
+        txid: TxId,
+    ) -> Result<Vec<(Self::NoteRef, Amount, Option<Memo>, bool)>, Self::Error> {
+        wallet::get_received_notes_for_tx(self, txid)
+    }
+
     fn get_commitment_tree(
         &self,
         block_height: BlockHeight,
@@ -231,6 +572,10 @@ impl<P: consensus::Parameters> WalletRead for WalletDb<P> {
         wallet::get_commitment_tree(self, block_height)
     }
 
+    fn get_tree_size(&self, block_height: BlockHeight) -> Result<Option<u64>, Self::Error> {
+        wallet::get_tree_size(self, block_height)
+    }
+
     #[allow(clippy::type_complexity)]
     fn get_witnesses(
         &self,
@@ -239,16 +584,91 @@ impl<P: consensus::Parameters> WalletRead for WalletDb<P> {
         wallet::get_witnesses(self, block_height)
     }
 
+    fn get_witnesses_for(
+        &self,
+        note_ids: &[Self::NoteRef],
+        block_height: BlockHeight,
+    ) -> Result<Vec<(Self::NoteRef, IncrementalWitness<Node>)>, Self::Error> {
+        wallet::get_witnesses_for(self, note_ids, block_height)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_tip_witnesses(
+        &self,
+    ) -> Result<Option<(BlockHeight, Vec<(Self::NoteRef, IncrementalWitness<Node>)>)>, Self::Error>
+    {
+        // Determine the tip and fetch its witnesses within a single read snapshot, so a
+        // concurrent writer cannot advance the tip in between the two queries.
+        self.with_read_snapshot(|snapshot| {
+            wallet::get_max_scanned_height(snapshot)?
+                .map(|tip_height| {
+                    Ok((tip_height, wallet::get_witnesses(snapshot, tip_height)?))
+                })
+                .transpose()
+        })
+    }
+
+    fn verify_witness(
+        &self,
+        note: Self::NoteRef,
+        at_height: BlockHeight,
+    ) -> Result<bool, Self::Error> {
+        wallet::verify_witness(self, note, at_height)
+    }
+
     fn get_nullifiers(&self) -> Result<Vec<(AccountId, Nullifier)>, Self::Error> {
         wallet::get_nullifiers(self)
     }
 
+    fn find_note_by_nullifier(
+        &self,
+        nf: &Nullifier,
+    ) -> Result<Option<(AccountId, Self::NoteRef)>, Self::Error> {
+        wallet::find_note_by_nullifier(self, nf)
+    }
+
+    fn find_conflicting_nullifiers(&self) -> Result<Vec<Nullifier>, Self::Error> {
+        wallet::find_conflicting_nullifiers(self)
+    }
+
     fn get_spendable_notes(
         &self,
         account: AccountId,
         anchor_height: BlockHeight,
+        verify: bool,
     ) -> Result<Vec<SpendableNote>, Self::Error> {
-        wallet::transact::get_spendable_notes(self, account, anchor_height)
+        wallet::transact::get_spendable_notes(self, account, anchor_height, verify)
+    }
+
+    fn get_spendable_notes_paged(
+        &self,
+        account: AccountId,
+        anchor_height: BlockHeight,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<SpendableNote>, usize), Self::Error> {
+        wallet::transact::get_spendable_notes_paged(self, account, anchor_height, offset, limit)
+    }
+
+    fn get_notes_page(
+        &self,
+        account: AccountId,
+        anchor_height: BlockHeight,
+        after: Option<Self::NoteRef>,
+        limit: usize,
+    ) -> Result<Vec<(Self::NoteRef, SpendableNote)>, Self::Error> {
+        let after = match after {
+            None => None,
+            Some(NoteId::ReceivedNoteId(id_note)) => Some(id_note),
+            Some(NoteId::SentNoteId(_)) => return Err(SqliteClientError::InvalidNoteId),
+            #[cfg(feature = "transparent-inputs")]
+            Some(NoteId::TransparentUtxoId(_)) => return Err(SqliteClientError::InvalidNoteId),
+        };
+        let page = wallet::transact::get_notes_page(self, account, anchor_height, after, limit)?;
+        Ok(page
+            .into_iter()
+            .map(|(id_note, note)| (NoteId::ReceivedNoteId(id_note), note))
+            .collect())
     }
 
     fn select_spendable_notes(
@@ -256,8 +676,38 @@ impl<P: consensus::Parameters> WalletRead for WalletDb<P> {
         account: AccountId,
         target_value: Amount,
         anchor_height: BlockHeight,
+        max_overselect: Option<Amount>,
+        exclude_unmined_change: bool,
+        exclude: &[Self::NoteRef],
+        strategy: NoteSelectionStrategy,
     ) -> Result<Vec<SpendableNote>, Self::Error> {
-        wallet::transact::select_spendable_notes(self, account, target_value, anchor_height)
+        wallet::transact::select_spendable_notes(
+            self,
+            account,
+            target_value,
+            anchor_height,
+            max_overselect,
+            exclude_unmined_change,
+            exclude,
+            strategy,
+        )
+    }
+
+    fn get_note_value_distribution(
+        &self,
+        account: AccountId,
+        anchor_height: BlockHeight,
+    ) -> Result<Vec<(Amount, usize)>, Self::Error> {
+        wallet::transact::get_note_value_distribution(self, account, anchor_height)
+    }
+
+    #[cfg(feature = "transparent-inputs")]
+    fn get_spendable_transparent_utxos(
+        &self,
+        address: &TransparentAddress,
+        anchor_height: BlockHeight,
+    ) -> Result<Vec<WalletTransparentOutput>, Self::Error> {
+        wallet::transparent::get_spendable_transparent_utxos(self, address, anchor_height)
     }
 }
 
@@ -271,6 +721,7 @@ impl<P: consensus::Parameters> WalletRead for WalletDb<P> {
 pub struct DataConnStmtCache<'a, P> {
     wallet_db: &'a WalletDb<P>,
     stmt_insert_block: Statement<'a>,
+    stmt_update_chain_tip: Statement<'a>,
 
     stmt_insert_tx_meta: Statement<'a>,
     stmt_update_tx_meta: Statement<'a>,
@@ -278,6 +729,7 @@ pub struct DataConnStmtCache<'a, P> {
     stmt_insert_tx_data: Statement<'a>,
     stmt_update_tx_data: Statement<'a>,
     stmt_select_tx_ref: Statement<'a>,
+    stmt_mark_tx_replaced: Statement<'a>,
 
     stmt_mark_recived_note_spent: Statement<'a>,
 
@@ -302,24 +754,78 @@ impl<'a, P: consensus::Parameters> WalletRead for DataConnStmtCache<'a, P> {
         self.wallet_db.block_height_extrema()
     }
 
+    fn get_wallet_birthday(&self) -> Result<Option<BlockHeight>, Self::Error> {
+        self.wallet_db.get_wallet_birthday()
+    }
+
+    fn get_max_scanned_height(&self) -> Result<Option<BlockHeight>, Self::Error> {
+        self.wallet_db.get_max_scanned_height()
+    }
+
     fn get_block_hash(&self, block_height: BlockHeight) -> Result<Option<BlockHash>, Self::Error> {
         self.wallet_db.get_block_hash(block_height)
     }
 
+    fn get_max_height_hash(&self) -> Result<Option<(BlockHeight, BlockHash)>, Self::Error> {
+        self.wallet_db.get_max_height_hash()
+    }
+
+    fn get_tip_block_time(&self) -> Result<Option<u32>, Self::Error> {
+        self.wallet_db.get_tip_block_time()
+    }
+
+    fn estimate_block_time(&self, height: BlockHeight) -> Result<Option<u32>, Self::Error> {
+        self.wallet_db.estimate_block_time(height)
+    }
+
     fn get_tx_height(&self, txid: TxId) -> Result<Option<BlockHeight>, Self::Error> {
         self.wallet_db.get_tx_height(txid)
     }
 
+    fn get_transaction(&self, txid: TxId) -> Result<Option<Transaction>, Self::Error> {
+        self.wallet_db.get_transaction(txid)
+    }
+
+    fn get_sent_tx_proposal_id(&self, txid: TxId) -> Result<Option<String>, Self::Error> {
+        self.wallet_db.get_sent_tx_proposal_id(txid)
+    }
+
+    fn get_transactions(
+        &self,
+        limit: usize,
+        offset: usize,
+        tip_height: Option<BlockHeight>,
+    ) -> Result<Vec<WalletTransaction>, Self::Error> {
+        self.wallet_db.get_transactions(limit, offset, tip_height)
+    }
+
     fn get_extended_full_viewing_keys(
         &self,
     ) -> Result<HashMap<AccountId, ExtendedFullViewingKey>, Self::Error> {
         self.wallet_db.get_extended_full_viewing_keys()
     }
 
+    fn get_unified_full_viewing_keys(
+        &self,
+    ) -> Result<HashMap<AccountId, UnifiedFullViewingKey>, Self::Error> {
+        self.wallet_db.get_unified_full_viewing_keys()
+    }
+
+    fn check_integrity(&self) -> Result<Vec<IntegrityWarning<Self::NoteRef>>, Self::Error> {
+        self.wallet_db.check_integrity()
+    }
+
     fn get_address(&self, account: AccountId) -> Result<Option<PaymentAddress>, Self::Error> {
         self.wallet_db.get_address(account)
     }
 
+    fn get_current_address(
+        &self,
+        account: AccountId,
+    ) -> Result<Option<PaymentAddress>, Self::Error> {
+        self.wallet_db.get_current_address(account)
+    }
+
     fn is_valid_account_extfvk(
         &self,
         account: AccountId,
@@ -336,10 +842,58 @@ impl<'a, P: consensus::Parameters> WalletRead for DataConnStmtCache<'a, P> {
         self.wallet_db.get_balance_at(account, anchor_height)
     }
 
+    fn get_balances_at(
+        &self,
+        anchor_height: BlockHeight,
+    ) -> Result<HashMap<AccountId, Amount>, Self::Error> {
+        self.wallet_db.get_balances_at(anchor_height)
+    }
+
+    fn get_sent_recipients(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<(RecipientAddress, Amount)>, Self::Error> {
+        self.wallet_db.get_sent_recipients(account)
+    }
+
     fn get_memo(&self, id_note: Self::NoteRef) -> Result<Memo, Self::Error> {
         self.wallet_db.get_memo(id_note)
     }
 
+    fn note_has_memo(&self, id_note: Self::NoteRef) -> Result<bool, Self::Error> {
+        self.wallet_db.note_has_memo(id_note)
+    }
+
+    fn get_sent_memo_for(
+        &self,
+        txid: TxId,
+        output_index: usize,
+    ) -> Result<Option<Memo>, Self::Error> {
+        self.wallet_db.get_sent_memo_for(txid, output_index)
+    }
+
+    fn get_memo_conversations(
+        &self,
+        account: AccountId,
+    ) -> Result<Vec<(RecipientAddress, Vec<Memo>)>, Self::Error> {
+        self.wallet_db.get_memo_conversations(account)
+    }
+
+    fn get_spending_tx(&self, note: Self::NoteRef) -> Result<Option<Self::TxRef>, Self::Error> {
+        self.wallet_db.get_spending_tx(note)
+    }
+
+    fn get_tx_label(&self, txid: TxId) -> Result<Option<String>, Self::Error> {
+        self.wallet_db.get_tx_label(txid)
+    }
+
+    fn get_received_notes_for_tx(
+        &self,
+        txid: TxId,
+    ) -> Result<Vec<(Self::NoteRef, Amount, Option<Memo>, bool)>, Self::Error> {
+        self.wallet_db.get_received_notes_for_tx(txid)
+    }
+
     fn get_commitment_tree(
         &self,
         block_height: BlockHeight,
@@ -347,6 +901,10 @@ impl<'a, P: consensus::Parameters> WalletRead for DataConnStmtCache<'a, P> {
         self.wallet_db.get_commitment_tree(block_height)
     }
 
+    fn get_tree_size(&self, block_height: BlockHeight) -> Result<Option<u64>, Self::Error> {
+        self.wallet_db.get_tree_size(block_height)
+    }
+
     #[allow(clippy::type_complexity)]
     fn get_witnesses(
         &self,
@@ -355,16 +913,75 @@ impl<'a, P: consensus::Parameters> WalletRead for DataConnStmtCache<'a, P> {
         self.wallet_db.get_witnesses(block_height)
     }
 
+    fn get_witnesses_for(
+        &self,
+        note_ids: &[Self::NoteRef],
+        block_height: BlockHeight,
+    ) -> Result<Vec<(Self::NoteRef, IncrementalWitness<Node>)>, Self::Error> {
+        self.wallet_db.get_witnesses_for(note_ids, block_height)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn get_tip_witnesses(
+        &self,
+    ) -> Result<Option<(BlockHeight, Vec<(Self::NoteRef, IncrementalWitness<Node>)>)>, Self::Error>
+    {
+        self.wallet_db.get_tip_witnesses()
+    }
+
+    fn verify_witness(
+        &self,
+        note: Self::NoteRef,
+        at_height: BlockHeight,
+    ) -> Result<bool, Self::Error> {
+        self.wallet_db.verify_witness(note, at_height)
+    }
+
     fn get_nullifiers(&self) -> Result<Vec<(AccountId, Nullifier)>, Self::Error> {
         self.wallet_db.get_nullifiers()
     }
 
+    fn find_note_by_nullifier(
+        &self,
+        nf: &Nullifier,
+    ) -> Result<Option<(AccountId, Self::NoteRef)>, Self::Error> {
+        self.wallet_db.find_note_by_nullifier(nf)
+    }
+
+    fn find_conflicting_nullifiers(&self) -> Result<Vec<Nullifier>, Self::Error> {
+        self.wallet_db.find_conflicting_nullifiers()
+    }
+
     fn get_spendable_notes(
         &self,
         account: AccountId,
         anchor_height: BlockHeight,
+        verify: bool,
     ) -> Result<Vec<SpendableNote>, Self::Error> {
-        self.wallet_db.get_spendable_notes(account, anchor_height)
+        self.wallet_db
+            .get_spendable_notes(account, anchor_height, verify)
+    }
+
+    fn get_spendable_notes_paged(
+        &self,
+        account: AccountId,
+        anchor_height: BlockHeight,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<SpendableNote>, usize), Self::Error> {
+        self.wallet_db
+            .get_spendable_notes_paged(account, anchor_height, offset, limit)
+    }
+
+    fn get_notes_page(
+        &self,
+        account: AccountId,
+        anchor_height: BlockHeight,
+        after: Option<Self::NoteRef>,
+        limit: usize,
+    ) -> Result<Vec<(Self::NoteRef, SpendableNote)>, Self::Error> {
+        self.wallet_db
+            .get_notes_page(account, anchor_height, after, limit)
     }
 
     fn select_spendable_notes(
@@ -372,9 +989,39 @@ impl<'a, P: consensus::Parameters> WalletRead for DataConnStmtCache<'a, P> {
         account: AccountId,
         target_value: Amount,
         anchor_height: BlockHeight,
+        max_overselect: Option<Amount>,
+        exclude_unmined_change: bool,
+        exclude: &[Self::NoteRef],
+        strategy: NoteSelectionStrategy,
     ) -> Result<Vec<SpendableNote>, Self::Error> {
+        self.wallet_db.select_spendable_notes(
+            account,
+            target_value,
+            anchor_height,
+            max_overselect,
+            exclude_unmined_change,
+            exclude,
+            strategy,
+        )
+    }
+
+    fn get_note_value_distribution(
+        &self,
+        account: AccountId,
+        anchor_height: BlockHeight,
+    ) -> Result<Vec<(Amount, usize)>, Self::Error> {
+        self.wallet_db
+            .get_note_value_distribution(account, anchor_height)
+    }
+
+    #[cfg(feature = "transparent-inputs")]
+    fn get_spendable_transparent_utxos(
+        &self,
+        address: &TransparentAddress,
+        anchor_height: BlockHeight,
+    ) -> Result<Vec<WalletTransparentOutput>, Self::Error> {
         self.wallet_db
-            .select_spendable_notes(account, target_value, anchor_height)
+            .get_spendable_transparent_utxos(address, anchor_height)
     }
 }
 
@@ -406,63 +1053,246 @@ impl<'a, P: consensus::Parameters> DataConnStmtCache<'a, P> {
     }
 }
 
-impl<'a, P: consensus::Parameters> WalletWrite for DataConnStmtCache<'a, P> {
+impl<'a, P: consensus::Parameters> DataConnStmtCache<'a, P> {
+    /// Persists a single block's worth of scanned data, without pruning witnesses or
+    /// expiring notes. Must be called from within a transaction; the caller is
+    /// responsible for doing so exactly once regardless of how many blocks are being
+    /// advanced, and for pruning witnesses and expiring notes afterwards.
     #[allow(clippy::type_complexity)]
-    fn advance_by_block(
+    fn advance_by_block_internal(
         &mut self,
         block: &PrunedBlock,
-        updated_witnesses: &[(Self::NoteRef, IncrementalWitness<Node>)],
-    ) -> Result<Vec<(Self::NoteRef, IncrementalWitness<Node>)>, Self::Error> {
-        // database updates for each block are transactional
-        self.transactionally(|up| {
-            // Insert the block into the database.
-            wallet::insert_block(
-                up,
-                block.block_height,
-                block.block_hash,
-                block.block_time,
-                &block.commitment_tree,
-            )?;
-
-            let mut new_witnesses = vec![];
-            for tx in block.transactions {
-                let tx_row = wallet::put_tx_meta(up, &tx, block.block_height)?;
-
-                // Mark notes as spent and remove them from the scanning cache
-                for spend in &tx.shielded_spends {
-                    wallet::mark_spent(up, tx_row, &spend.nf)?;
-                }
+        updated_witnesses: &[(<Self as WalletRead>::NoteRef, IncrementalWitness<Node>)],
+    ) -> Result<
+        (
+            Vec<(<Self as WalletRead>::NoteRef, IncrementalWitness<Node>)>,
+            BlockAdvanceCounts,
+        ),
+        SqliteClientError,
+    > {
+        // Reject blocks that don't chain from our current tip, so that a reorg the
+        // caller hasn't rewound past doesn't silently fork the persisted chain.
+        if let Some((_, tip_hash)) = self.get_max_height_hash()? {
+            if block.prev_hash != tip_hash {
+                return Err(SqliteClientError::BlockConflict {
+                    at_height: block.block_height,
+                });
+            }
+        }
 
-                for output in &tx.shielded_outputs {
-                    let received_note_id = wallet::put_received_note(up, output, tx_row)?;
+        // Count the notes this block adds to the wallet up front, so it can be stored
+        // alongside the block for a running "X notes found so far" progress indicator
+        // without an aggregate scan over every scanned block on each read.
+        let received_note_count: usize = block
+            .transactions
+            .iter()
+            .map(|tx| tx.shielded_outputs.len())
+            .sum();
+
+        // Insert the block into the database.
+        wallet::insert_block(
+            self,
+            block.block_height,
+            block.block_hash,
+            block.block_time,
+            &block.commitment_tree,
+            received_note_count,
+        )?;
+
+        let mut new_witnesses = vec![];
+        let mut notes_removed = 0;
+        for tx in block.transactions {
+            let tx_row = wallet::put_tx_meta(self, &tx, block.block_height)?;
+
+            // Mark notes as spent and remove them from the scanning cache, in a single
+            // UPDATE per transaction rather than one per spend.
+            let nfs: Vec<_> = tx.shielded_spends.iter().map(|spend| spend.nf).collect();
+            let spent = wallet::mark_spent_batch(self.wallet_db, tx_row, &nfs)?;
+            if spent != nfs.len() {
+                return Err(SqliteClientError::CorruptedData(format!(
+                    "Expected to mark {} of our own notes as spent by transaction {}, but only found {}",
+                    nfs.len(),
+                    tx_row,
+                    spent
+                )));
+            }
+            notes_removed += spent;
 
-                    // Save witness for note.
-                    new_witnesses.push((received_note_id, output.witness.clone()));
-                }
+            for output in &tx.shielded_outputs {
+                let received_note_id = wallet::put_received_note(self, output, tx_row)?;
+
+                // Save witness for note.
+                new_witnesses.push((received_note_id, output.witness.clone()));
             }
+        }
 
-            // Insert current new_witnesses into the database.
-            for (received_note_id, witness) in updated_witnesses.iter().chain(new_witnesses.iter())
-            {
-                if let NoteId::ReceivedNoteId(rnid) = *received_note_id {
-                    wallet::insert_witness(up, rnid, witness, block.block_height)?;
-                } else {
-                    return Err(SqliteClientError::InvalidNoteId);
-                }
+        // Insert current new_witnesses into the database.
+        for (received_note_id, witness) in updated_witnesses.iter().chain(new_witnesses.iter()) {
+            if let NoteId::ReceivedNoteId(rnid) = *received_note_id {
+                wallet::insert_witness(self, rnid, witness, block.block_height)?;
+            } else {
+                return Err(SqliteClientError::InvalidNoteId);
             }
+        }
 
-            // Prune the stored witnesses (we only expect rollbacks of at most 100 blocks).
-            let below_height = if block.block_height < BlockHeight::from(100) {
+        let counts = BlockAdvanceCounts {
+            notes_added: new_witnesses.len(),
+            notes_removed,
+        };
+        Ok((new_witnesses, counts))
+    }
+
+    /// Persists a transaction that spends notes from this wallet, without wrapping the
+    /// writes in a transaction. Must be called from within a transaction; shared by
+    /// [`WalletWrite::store_sent_tx`] and [`WalletWrite::store_replacement_tx`], the
+    /// latter of which has additional bookkeeping to perform in the same transaction.
+    fn store_sent_tx_internal(
+        &mut self,
+        sent_tx: &SentTransaction,
+    ) -> Result<<Self as WalletRead>::TxRef, SqliteClientError> {
+        let tx_ref = wallet::put_tx_data(
+            self,
+            &sent_tx.tx,
+            Some(sent_tx.created),
+            sent_tx.proposal_id.as_deref(),
+        )?;
+
+        // Backfill the fee from the transaction's value balance where possible, so that
+        // v_transactions-style aggregates over wallet-created transactions aren't left
+        // with a NULL fee.
+        if let Some(fee) = wallet::compute_transaction_fee(sent_tx.tx) {
+            wallet::set_transaction_fee(self.wallet_db, sent_tx.tx.txid(), fee)?;
+        }
+
+        // Mark notes as spent.
+        //
+        // This locks the notes so they aren't selected again by a subsequent call to
+        // create_spend_to_address() before this transaction has been mined (at which point the notes
+        // get re-marked as spent).
+        //
+        // Assumes that create_spend_to_address() will never be called in parallel, which is a
+        // reasonable assumption for a light client such as a mobile phone.
+        for spend in &sent_tx.tx.shielded_spends {
+            wallet::mark_spent(self, tx_ref, &spend.nullifier)?;
+        }
+
+        // Check that `output_index` actually refers to an output of the recipient's
+        // pool in this transaction, so that a caller's mistake doesn't silently
+        // record the sent note against the wrong output.
+        let output_in_range = match sent_tx.recipient_address {
+            RecipientAddress::Shielded(_) => {
+                sent_tx.output_index < sent_tx.tx.shielded_outputs.len()
+            }
+            RecipientAddress::Transparent(_) => sent_tx.output_index < sent_tx.tx.vout.len(),
+        };
+        if !output_in_range {
+            return Err(SqliteClientError::InvalidOutputIndex);
+        }
+
+        wallet::insert_sent_note(
+            self,
+            tx_ref,
+            sent_tx.output_index,
+            sent_tx.account,
+            sent_tx.recipient_address,
+            sent_tx.value,
+            sent_tx.memo.as_ref(),
+        )?;
+
+        // Record any transparent change returned to an address we control, so the
+        // wallet's transparent balance stays correct even before this transaction
+        // is mined.
+        #[cfg(feature = "transparent-inputs")]
+        for (index, output) in sent_tx.tx.vout.iter().enumerate() {
+            wallet::transparent::put_transparent_change(self, &sent_tx.tx.txid(), index, output)?;
+        }
+
+        // Return the row number of the transaction, so the caller can fetch it for sending.
+        Ok(tx_ref)
+    }
+}
+
+impl<'a, P: consensus::Parameters> WalletWrite for DataConnStmtCache<'a, P> {
+    #[allow(clippy::type_complexity)]
+    fn advance_by_block(
+        &mut self,
+        block: &PrunedBlock,
+        updated_witnesses: &[(Self::NoteRef, IncrementalWitness<Node>)],
+    ) -> Result<
+        (
+            Vec<(Self::NoteRef, IncrementalWitness<Node>)>,
+            BlockAdvanceCounts,
+        ),
+        Self::Error,
+    > {
+        let block_height = block.block_height;
+
+        // Newly-scanned notes may reuse `id_note` values freed up by a prior rewind, so
+        // the memo cache is invalidated eagerly rather than only evicting stale entries.
+        self.wallet_db.memo_cache.borrow_mut().clear();
+
+        // database updates for each block are transactional
+        self.transactionally(|up| {
+            let result = up.advance_by_block_internal(block, updated_witnesses)?;
+
+            // Prune the stored witnesses; a rewind past this point is rejected by
+            // `rewind_to_height` with `ReorgTooDeep` rather than silently mishandled.
+            let max_reorg_depth = up.wallet_db.max_reorg_depth;
+            let below_height = if block_height < BlockHeight::from(max_reorg_depth) {
                 BlockHeight::from(0)
             } else {
-                block.block_height - 100
+                block_height - max_reorg_depth
             };
             wallet::prune_witnesses(up, below_height)?;
 
             // Update now-expired transactions that didn't get mined.
-            wallet::update_expired_notes(up, block.block_height)?;
+            wallet::update_expired_notes(up, block_height)?;
+
+            Ok(result)
+        })
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn advance_by_blocks(
+        &mut self,
+        blocks: &[PrunedBlock],
+        updated_witnesses: &[(Self::NoteRef, IncrementalWitness<Node>)],
+    ) -> Result<
+        (
+            Vec<(Self::NoteRef, IncrementalWitness<Node>)>,
+            BlockAdvanceCounts,
+        ),
+        Self::Error,
+    > {
+        self.wallet_db.memo_cache.borrow_mut().clear();
+
+        // All blocks in the slice are persisted within a single transaction, and
+        // witnesses are pruned and expired notes are updated only once, using the
+        // height of the last block, rather than once per block.
+        self.transactionally(|up| {
+            let mut witnesses = updated_witnesses.to_vec();
+            let mut counts = BlockAdvanceCounts::default();
+            for block in blocks {
+                let (new_witnesses, block_counts) =
+                    up.advance_by_block_internal(block, &witnesses)?;
+                witnesses.extend(new_witnesses);
+                counts.notes_added += block_counts.notes_added;
+                counts.notes_removed += block_counts.notes_removed;
+            }
+
+            if let Some(last_block) = blocks.last() {
+                let max_reorg_depth = up.wallet_db.max_reorg_depth;
+                let below_height = if last_block.block_height < BlockHeight::from(max_reorg_depth)
+                {
+                    BlockHeight::from(0)
+                } else {
+                    last_block.block_height - max_reorg_depth
+                };
+                wallet::prune_witnesses(up, below_height)?;
+                wallet::update_expired_notes(up, last_block.block_height)?;
+            }
 
-            Ok(new_witnesses)
+            Ok((witnesses, counts))
         })
     }
 
@@ -471,7 +1301,7 @@ impl<'a, P: consensus::Parameters> WalletWrite for DataConnStmtCache<'a, P> {
         received_tx: &ReceivedTransaction,
     ) -> Result<Self::TxRef, Self::Error> {
         self.transactionally(|up| {
-            let tx_ref = wallet::put_tx_data(up, received_tx.tx, None)?;
+            let tx_ref = wallet::put_tx_data(up, received_tx.tx, None, None)?;
 
             for output in received_tx.outputs {
                 if output.outgoing {
@@ -485,41 +1315,118 @@ impl<'a, P: consensus::Parameters> WalletWrite for DataConnStmtCache<'a, P> {
         })
     }
 
+    fn store_received_txs(
+        &mut self,
+        received_txs: &[ReceivedTransaction],
+    ) -> Result<Vec<Self::TxRef>, Self::Error> {
+        // Wrap the whole batch in a single transaction, rather than one per received
+        // transaction, so that a large import (e.g. a rescan from raw transaction data)
+        // doesn't pay a BEGIN/COMMIT per transaction.
+        self.transactionally(|up| {
+            received_txs
+                .iter()
+                .map(|received_tx| {
+                    let tx_ref = wallet::put_tx_data(up, received_tx.tx, None, None)?;
+
+                    for output in received_tx.outputs {
+                        if output.outgoing {
+                            wallet::put_sent_note(up, output, tx_ref)?;
+                        } else {
+                            wallet::put_received_note(up, output, tx_ref)?;
+                        }
+                    }
+
+                    Ok(tx_ref)
+                })
+                .collect()
+        })
+    }
+
     fn store_sent_tx(&mut self, sent_tx: &SentTransaction) -> Result<Self::TxRef, Self::Error> {
+        // Update the database atomically, to ensure the result is internally consistent.
+        self.transactionally(|up| up.store_sent_tx_internal(sent_tx))
+    }
+
+    fn set_transaction_fee(&mut self, txid: TxId, fee: Amount) -> Result<(), Self::Error> {
+        wallet::set_transaction_fee(self.wallet_db, txid, fee)
+    }
+
+    fn set_tx_label(&mut self, txid: TxId, label: String) -> Result<(), Self::Error> {
+        wallet::set_tx_label(self.wallet_db, txid, label)
+    }
+
+    fn set_tx_broadcast(&mut self, tx_ref: Self::TxRef, success: bool) -> Result<(), Self::Error> {
+        wallet::set_tx_broadcast(self.wallet_db, tx_ref, success)
+    }
+
+    fn store_replacement_tx(
+        &mut self,
+        old: Self::TxRef,
+        new: &SentTransaction,
+    ) -> Result<Self::TxRef, Self::Error> {
         // Update the database atomically, to ensure the result is internally consistent.
         self.transactionally(|up| {
-            let tx_ref = wallet::put_tx_data(up, &sent_tx.tx, Some(sent_tx.created))?;
+            let tx_ref = up.store_sent_tx_internal(new)?;
 
-            // Mark notes as spent.
-            //
-            // This locks the notes so they aren't selected again by a subsequent call to
-            // create_spend_to_address() before this transaction has been mined (at which point the notes
-            // get re-marked as spent).
+            // Record the fee-bump linkage so that a caller walking `transactions` can
+            // follow a stuck transaction to whatever replaced it.
             //
-            // Assumes that create_spend_to_address() will never be called in parallel, which is a
-            // reasonable assumption for a light client such as a mobile phone.
-            for spend in &sent_tx.tx.shielded_spends {
-                wallet::mark_spent(up, tx_ref, &spend.nullifier)?;
-            }
+            // `store_sent_tx_internal` has already re-marked `old`'s spent notes as
+            // spent by `tx_ref` (nullifiers are shared with a replacement transaction),
+            // so `update_expired_notes` will no longer free them on `old`'s expiry: it
+            // only inspects the transaction a note's `spent` column currently points
+            // to, which is now the replacement rather than the stuck original.
+            wallet::mark_tx_replaced(up, old, tx_ref)?;
 
-            wallet::insert_sent_note(
-                up,
-                tx_ref,
-                sent_tx.output_index,
-                sent_tx.account,
-                sent_tx.recipient_address,
-                sent_tx.value,
-                sent_tx.memo.as_ref(),
-            )?;
-
-            // Return the row number of the transaction, so the caller can fetch it for sending.
             Ok(tx_ref)
         })
     }
 
+    #[cfg(feature = "transparent-inputs")]
+    fn put_received_transparent_utxo(
+        &mut self,
+        utxo: &WalletTransparentOutput,
+    ) -> Result<Self::NoteRef, Self::Error> {
+        wallet::transparent::put_received_transparent_utxo(self, utxo)
+            .map(NoteId::TransparentUtxoId)
+    }
+
+    fn import_viewing_account(
+        &mut self,
+        extfvk: &ExtendedFullViewingKey,
+        birthday: BlockHeight,
+    ) -> Result<AccountId, Self::Error> {
+        wallet::init::import_viewing_account(self.wallet_db, extfvk, birthday)
+    }
+
+    fn backfill_nullifiers(
+        &mut self,
+        account: AccountId,
+        extfvk: &ExtendedFullViewingKey,
+    ) -> Result<usize, Self::Error> {
+        wallet::backfill_nullifiers(self.wallet_db, account, extfvk)
+    }
+
+    fn get_next_available_address(
+        &mut self,
+        account: AccountId,
+    ) -> Result<PaymentAddress, Self::Error> {
+        wallet::get_next_available_address(self.wallet_db, account)
+    }
+
     fn rewind_to_height(&mut self, block_height: BlockHeight) -> Result<(), Self::Error> {
+        self.wallet_db.memo_cache.borrow_mut().clear();
         wallet::rewind_to_height(self.wallet_db, block_height)
     }
+
+    fn prune_blocks_below(&mut self, block_height: BlockHeight) -> Result<(), Self::Error> {
+        wallet::prune_blocks_below(self.wallet_db, block_height)
+    }
+
+    fn reset_sync_state(&mut self) -> Result<(), Self::Error> {
+        self.wallet_db.memo_cache.borrow_mut().clear();
+        self.transactionally(|up| wallet::reset_sync_state(up.wallet_db))
+    }
 }
 
 /// A wrapper for the SQLite connection to the block cache database.
@@ -561,7 +1468,7 @@ mod tests {
     use ff::PrimeField;
     use group::GroupEncoding;
     use protobuf::Message;
-    use rand_core::{OsRng, RngCore};
+    use rand_core::{CryptoRng, OsRng, RngCore};
     use rusqlite::params;
 
     use zcash_client_backend::proto::compact_formats::{
@@ -580,7 +1487,7 @@ mod tests {
         zip32::ExtendedFullViewingKey,
     };
 
-    use super::BlockDb;
+    use super::{BlockDb, NoteId};
 
     #[cfg(feature = "mainnet")]
     pub(crate) fn network() -> Network {
@@ -606,6 +1513,23 @@ mod tests {
             .unwrap()
     }
 
+    #[test]
+    fn note_id_stable_string_round_trips() {
+        for note_id in [
+            NoteId::SentNoteId(5),
+            NoteId::ReceivedNoteId(5),
+            #[cfg(feature = "transparent-inputs")]
+            NoteId::TransparentUtxoId(5),
+        ] {
+            let s = note_id.to_stable_string();
+            assert_eq!(NoteId::from_stable_string(&s).unwrap(), note_id);
+        }
+
+        assert!(NoteId::from_stable_string("Sent Note 5").is_err());
+        assert!(NoteId::from_stable_string("r:not-a-number").is_err());
+        assert!(NoteId::from_stable_string("x:5").is_err());
+    }
+
     /// Create a fake CompactBlock at the given height, containing a single output paying
     /// the given address. Returns the CompactBlock and the nullifier for the new note.
     pub(crate) fn fake_compact_block(
@@ -613,11 +1537,23 @@ mod tests {
         prev_hash: BlockHash,
         extfvk: ExtendedFullViewingKey,
         value: Amount,
+    ) -> (CompactBlock, Nullifier) {
+        fake_compact_block_with_rng(height, prev_hash, extfvk, value, &mut OsRng)
+    }
+
+    /// Create a fake CompactBlock at the given height, containing a single output paying
+    /// the given address, using the supplied RNG. Seeding the RNG (e.g. with a
+    /// `ChaChaRng`) gives byte-for-byte reproducible blocks across test runs.
+    pub(crate) fn fake_compact_block_with_rng<R: RngCore + CryptoRng>(
+        height: BlockHeight,
+        prev_hash: BlockHash,
+        extfvk: ExtendedFullViewingKey,
+        value: Amount,
+        mut rng: R,
     ) -> (CompactBlock, Nullifier) {
         let to = extfvk.default_address().unwrap().1;
 
         // Create a fake Note for the account
-        let mut rng = OsRng;
         let rseed = generate_random_rseed(&network(), height, &mut rng);
         let note = Note {
             g_d: to.diversifier().g_d().unwrap(),
@@ -655,17 +1591,86 @@ mod tests {
         (cb, note.nf(&extfvk.fvk.vk, 0))
     }
 
+    /// Create a fake CompactBlock at the given height, containing one output per
+    /// `(extfvk, value)` pair, each in its own transaction. Useful for exercising
+    /// multi-account attribution within a single block.
+    pub(crate) fn fake_compact_block_two_accounts(
+        height: BlockHeight,
+        prev_hash: BlockHash,
+        recipients: &[(ExtendedFullViewingKey, Amount)],
+    ) -> (CompactBlock, Vec<Nullifier>) {
+        let mut rng = OsRng;
+        let mut cb = CompactBlock::new();
+        cb.set_height(u64::from(height));
+        cb.hash.resize(32, 0);
+        rng.fill_bytes(&mut cb.hash);
+        cb.prevHash.extend_from_slice(&prev_hash.0);
+
+        let mut nullifiers = vec![];
+        for (extfvk, value) in recipients {
+            let to = extfvk.default_address().unwrap().1;
+
+            let rseed = generate_random_rseed(&network(), height, &mut rng);
+            let note = Note {
+                g_d: to.diversifier().g_d().unwrap(),
+                pk_d: *to.pk_d(),
+                value: (*value).into(),
+                rseed,
+            };
+            let encryptor = sapling_note_encryption::<_, Network>(
+                Some(extfvk.fvk.ovk),
+                note.clone(),
+                to,
+                MemoBytes::empty(),
+                &mut rng,
+            );
+            let cmu = note.cmu().to_repr().as_ref().to_vec();
+            let epk = encryptor.epk().to_bytes().to_vec();
+            let enc_ciphertext = encryptor.encrypt_note_plaintext();
+
+            let mut cout = CompactOutput::new();
+            cout.set_cmu(cmu);
+            cout.set_epk(epk);
+            cout.set_ciphertext(enc_ciphertext.as_ref()[..52].to_vec());
+            let mut ctx = CompactTx::new();
+            let mut txid = vec![0; 32];
+            rng.fill_bytes(&mut txid);
+            ctx.set_hash(txid);
+            ctx.outputs.push(cout);
+            cb.vtx.push(ctx);
+
+            nullifiers.push(note.nf(&extfvk.fvk.vk, 0));
+        }
+
+        (cb, nullifiers)
+    }
+
     /// Create a fake CompactBlock at the given height, spending a single note from the
     /// given address.
     pub(crate) fn fake_compact_block_spending(
+        height: BlockHeight,
+        prev_hash: BlockHash,
+        spent: (Nullifier, Amount),
+        extfvk: ExtendedFullViewingKey,
+        to: PaymentAddress,
+        value: Amount,
+    ) -> CompactBlock {
+        fake_compact_block_spending_with_rng(height, prev_hash, spent, extfvk, to, value, &mut OsRng)
+    }
+
+    /// Create a fake CompactBlock at the given height, spending a single note from the
+    /// given address, using the supplied RNG. Seeding the RNG (e.g. with a `ChaChaRng`)
+    /// gives byte-for-byte reproducible blocks across test runs.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn fake_compact_block_spending_with_rng<R: RngCore + CryptoRng>(
         height: BlockHeight,
         prev_hash: BlockHash,
         (nf, in_value): (Nullifier, Amount),
         extfvk: ExtendedFullViewingKey,
         to: PaymentAddress,
         value: Amount,
+        mut rng: R,
     ) -> CompactBlock {
-        let mut rng = OsRng;
         let rseed = generate_random_rseed(&network(), height, &mut rng);
 
         // Create a fake CompactBlock containing the note
@@ -710,7 +1715,9 @@ mod tests {
             let note = Note {
                 g_d: change_addr.diversifier().g_d().unwrap(),
                 pk_d: *change_addr.pk_d(),
-                value: (in_value - value).into(),
+                value: crate::wallet::checked_amount_sub(in_value, value)
+                    .expect("spent value should not exceed the value of the note being spent")
+                    .into(),
                 rseed,
             };
             let encryptor = sapling_note_encryption::<_, Network>(
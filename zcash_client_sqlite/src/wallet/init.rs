@@ -1,6 +1,6 @@
 //! Functions for initializing the various databases.
 
-use rusqlite::types::ToSql;
+use rusqlite::{params, types::ToSql};
 
 use zcash_primitives::{
     block::BlockHash,
@@ -8,7 +8,11 @@ use zcash_primitives::{
     zip32::ExtendedFullViewingKey,
 };
 
-use zcash_client_backend::encoding::encode_extended_full_viewing_key;
+use zcash_client_backend::{
+    encoding::{encode_extended_full_viewing_key, encode_unified_full_viewing_key},
+    keys::UnifiedFullViewingKey,
+    wallet::AccountId,
+};
 
 use crate::{address_from_extfvk, error::SqliteClientError, WalletDb};
 
@@ -33,7 +37,11 @@ pub fn init_wallet_db<P>(wdb: &WalletDb<P>) -> Result<(), rusqlite::Error> {
         "CREATE TABLE IF NOT EXISTS accounts (
             account INTEGER PRIMARY KEY,
             extfvk TEXT NOT NULL,
-            address TEXT NOT NULL
+            address TEXT NOT NULL,
+            birthday_height INTEGER,
+            diversifier_index_be BLOB,
+            current_address TEXT,
+            ufvk TEXT
         )",
         [],
     )?;
@@ -42,23 +50,56 @@ pub fn init_wallet_db<P>(wdb: &WalletDb<P>) -> Result<(), rusqlite::Error> {
             height INTEGER PRIMARY KEY,
             hash BLOB NOT NULL,
             time INTEGER NOT NULL,
-            sapling_tree BLOB NOT NULL
+            sapling_tree BLOB NOT NULL,
+            received_note_count INTEGER NOT NULL DEFAULT 0,
+            tree_size INTEGER NOT NULL DEFAULT 0
         )",
         [],
     )?;
+    // A single-row table caching the height and hash of the highest block in `blocks`,
+    // maintained alongside it so that the current tip can be read in O(1) rather than
+    // via a MIN/MAX aggregate scan over every scanned block.
+    wdb.conn.execute(
+        "CREATE TABLE IF NOT EXISTS chain_tip (
+            singleton INTEGER PRIMARY KEY CHECK (singleton = 0),
+            height INTEGER NOT NULL,
+            hash BLOB NOT NULL,
+            FOREIGN KEY (height) REFERENCES blocks(height)
+        )",
+        [],
+    )?;
+    // `fee` is part of the base schema rather than a later addition: this database has
+    // no versioned/reversible migration framework (no `up`/`down` steps, no
+    // `add_transaction_views` migration, and no transaction-summary views anywhere in
+    // this schema), so there is nothing here that a rollback would need to undo. If a
+    // migration framework and those views are introduced in the future, dropping them
+    // should be straightforward; dropping the `fee` column itself would require a table
+    // rebuild, since SQLite could not drop columns until 3.35.
     wdb.conn.execute(
         "CREATE TABLE IF NOT EXISTS transactions (
             id_tx INTEGER PRIMARY KEY,
-            txid BLOB NOT NULL UNIQUE,
+            txid BLOB NOT NULL,
             created TEXT,
             block INTEGER,
             tx_index INTEGER,
             expiry_height INTEGER,
             raw BLOB,
-            FOREIGN KEY (block) REFERENCES blocks(height)
+            proposal_id TEXT,
+            replaced_by INTEGER,
+            fee INTEGER,
+            broadcast TEXT,
+            FOREIGN KEY (block) REFERENCES blocks(height),
+            FOREIGN KEY (replaced_by) REFERENCES transactions(id_tx)
         )",
         [],
     )?;
+    // txid is not declared UNIQUE: a reorg that reintroduces a previously-seen
+    // transaction can briefly leave more than one row for the same txid before the
+    // stale row is pruned, and readers need to tolerate that rather than error.
+    wdb.conn.execute(
+        "CREATE INDEX IF NOT EXISTS transactions_txid ON transactions(txid)",
+        [],
+    )?;
     wdb.conn.execute(
         "CREATE TABLE IF NOT EXISTS received_notes (
             id_note INTEGER PRIMARY KEY,
@@ -92,6 +133,10 @@ pub fn init_wallet_db<P>(wdb: &WalletDb<P>) -> Result<(), rusqlite::Error> {
         [],
     )?;
     wdb.conn.execute(
+        // output_pool distinguishes which set of a transaction's outputs output_index
+        // indexes into (see wallet::{SAPLING_POOL, TRANSPARENT_POOL}); it defaults to the
+        // Sapling pool because that was the only pool this table recorded prior to its
+        // addition.
         "CREATE TABLE IF NOT EXISTS sent_notes (
             id_note INTEGER PRIMARY KEY,
             tx INTEGER NOT NULL,
@@ -100,12 +145,41 @@ pub fn init_wallet_db<P>(wdb: &WalletDb<P>) -> Result<(), rusqlite::Error> {
             address TEXT NOT NULL,
             value INTEGER NOT NULL,
             memo BLOB,
+            output_pool INTEGER NOT NULL DEFAULT 2,
             FOREIGN KEY (tx) REFERENCES transactions(id_tx),
             FOREIGN KEY (from_account) REFERENCES accounts(account),
             CONSTRAINT tx_output UNIQUE (tx, output_index)
         )",
         [],
     )?;
+    // height is NULL for transparent change recorded from a transaction we've created
+    // but not yet observed mined; it is populated once the transaction is confirmed.
+    #[cfg(feature = "transparent-inputs")]
+    wdb.conn.execute(
+        "CREATE TABLE IF NOT EXISTS utxos (
+            id_utxo INTEGER PRIMARY KEY,
+            address TEXT NOT NULL,
+            prevout_txid BLOB NOT NULL,
+            prevout_idx INTEGER NOT NULL,
+            script BLOB NOT NULL,
+            value_zat INTEGER NOT NULL,
+            height INTEGER,
+            spent_in_tx INTEGER,
+            FOREIGN KEY (spent_in_tx) REFERENCES transactions(id_tx),
+            CONSTRAINT utxo_outpoint UNIQUE (prevout_txid, prevout_idx)
+        )",
+        [],
+    )?;
+    // Deliberately not tied to `transactions` by a foreign key: unlike every other table
+    // here, a label is user-entered rather than chain-derived, so it must survive a
+    // rewind or rescan that clears the transactions it was attached to.
+    wdb.conn.execute(
+        "CREATE TABLE IF NOT EXISTS tx_labels (
+            txid BLOB NOT NULL PRIMARY KEY,
+            label TEXT NOT NULL
+        )",
+        [],
+    )?;
     Ok(())
 }
 
@@ -177,6 +251,133 @@ pub fn init_accounts_table<P: consensus::Parameters>(
     Ok(())
 }
 
+/// Initialises the data database with the given [`UnifiedFullViewingKey`]s.
+///
+/// Like [`init_accounts_table`], this requires an empty `accounts` table and `ufvks`
+/// **MUST** be arranged in account order. Unlike [`init_accounts_table`], the encoded
+/// unified full viewing key is also stored in the `ufvk` column, alongside the Sapling
+/// [`ExtendedFullViewingKey`] it wraps, so that a caller restoring from a UFVK string can
+/// be handed back the same string later.
+///
+/// # Examples
+///
+/// ```
+/// use tempfile::NamedTempFile;
+///
+/// use zcash_primitives::{
+///     consensus::Network,
+///     zip32::{ExtendedFullViewingKey, ExtendedSpendingKey}
+/// };
+///
+/// use zcash_client_backend::keys::UnifiedFullViewingKey;
+///
+/// use zcash_client_sqlite::{
+///     WalletDb,
+///     wallet::init::{init_accounts_table_ufvk, init_wallet_db}
+/// };
+///
+/// let data_file = NamedTempFile::new().unwrap();
+/// let db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
+/// init_wallet_db(&db_data).unwrap();
+///
+/// let extsk = ExtendedSpendingKey::master(&[]);
+/// let extfvk = ExtendedFullViewingKey::from(&extsk);
+/// let ufvks = [UnifiedFullViewingKey::from_sapling_extended_full_viewing_key(extfvk)];
+/// init_accounts_table_ufvk(&db_data, &ufvks).unwrap();
+/// ```
+///
+/// [`ExtendedFullViewingKey`]: zcash_primitives::zip32::ExtendedFullViewingKey
+/// [`UnifiedFullViewingKey`]: zcash_client_backend::keys::UnifiedFullViewingKey
+pub fn init_accounts_table_ufvk<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    ufvks: &[UnifiedFullViewingKey],
+) -> Result<(), SqliteClientError> {
+    let mut empty_check = wdb.conn.prepare("SELECT * FROM accounts LIMIT 1")?;
+    if empty_check.exists([])? {
+        return Err(SqliteClientError::TableNotEmpty);
+    }
+
+    // Insert accounts atomically
+    wdb.conn.execute("BEGIN IMMEDIATE", [])?;
+    for (account, ufvk) in ufvks.iter().enumerate() {
+        let extfvk_str =
+            encode_extended_full_viewing_key(wdb.params.hrp_sapling_extended_full_viewing_key(), ufvk.sapling());
+        let ufvk_str = encode_unified_full_viewing_key(&wdb.params, ufvk);
+        let address_str = address_from_extfvk(&wdb.params, ufvk.sapling());
+
+        wdb.conn.execute(
+            "INSERT INTO accounts (account, extfvk, address, ufvk)
+            VALUES (?, ?, ?, ?)",
+            [
+                (account as u32).to_sql()?,
+                extfvk_str.to_sql()?,
+                address_str.to_sql()?,
+                ufvk_str.to_sql()?,
+            ],
+        )?;
+    }
+    wdb.conn.execute("COMMIT", [])?;
+
+    Ok(())
+}
+
+/// Adds a single watch-only account to the wallet, tracked from the given birthday height.
+///
+/// Unlike [`init_accounts_table`], which requires an empty `accounts` table and a
+/// complete, ordered list of accounts, this may be called on a wallet that already
+/// contains accounts and historical data; the new account is assigned the next available
+/// account index. The birthday height is recorded so that callers know from which height
+/// they need to fetch and scan blocks in order to discover this account's funds.
+///
+/// # Examples
+///
+/// ```
+/// use tempfile::NamedTempFile;
+///
+/// use zcash_primitives::{
+///     consensus::{BlockHeight, Network},
+///     zip32::{ExtendedFullViewingKey, ExtendedSpendingKey}
+/// };
+///
+/// use zcash_client_sqlite::{
+///     WalletDb,
+///     wallet::init::{import_viewing_account, init_wallet_db}
+/// };
+///
+/// let data_file = NamedTempFile::new().unwrap();
+/// let db_data = WalletDb::for_path(data_file.path(), Network::TestNetwork).unwrap();
+/// init_wallet_db(&db_data).unwrap();
+///
+/// let extsk = ExtendedSpendingKey::master(&[]);
+/// let extfvk = ExtendedFullViewingKey::from(&extsk);
+/// let account = import_viewing_account(&db_data, &extfvk, BlockHeight::from(500_000)).unwrap();
+/// ```
+pub fn import_viewing_account<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    extfvk: &ExtendedFullViewingKey,
+    birthday: BlockHeight,
+) -> Result<AccountId, SqliteClientError> {
+    let account: u32 = wdb.conn.query_row(
+        "SELECT COALESCE(MAX(account), -1) + 1 FROM accounts",
+        [],
+        |row| row.get(0),
+    )?;
+
+    let extfvk_str = encode_extended_full_viewing_key(
+        wdb.params.hrp_sapling_extended_full_viewing_key(),
+        extfvk,
+    );
+    let address_str = address_from_extfvk(&wdb.params, extfvk);
+
+    wdb.conn.execute(
+        "INSERT INTO accounts (account, extfvk, address, birthday_height)
+        VALUES (?, ?, ?, ?)",
+        params![account, extfvk_str, address_str, u32::from(birthday)],
+    )?;
+
+    Ok(AccountId(account))
+}
+
 /// Initialises the data database with the given block.
 ///
 /// This enables a newly-created database to be immediately-usable, without needing to
@@ -245,9 +446,17 @@ mod tests {
         zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
     };
 
+    use zcash_client_backend::{
+        encoding::decode_unified_full_viewing_key,
+        keys::UnifiedFullViewingKey,
+    };
+
     use crate::{tests, wallet::get_address, AccountId, WalletDb};
 
-    use super::{init_accounts_table, init_blocks_table, init_wallet_db};
+    use super::{
+        import_viewing_account, init_accounts_table, init_accounts_table_ufvk, init_blocks_table,
+        init_wallet_db,
+    };
 
     #[test]
     fn init_accounts_table_only_works_once() {
@@ -312,4 +521,72 @@ mod tests {
         let pa = get_address(&db_data, AccountId(0)).unwrap();
         assert_eq!(pa.unwrap(), extsk.default_address().unwrap().1);
     }
+
+    #[test]
+    fn init_accounts_table_ufvk_round_trips_and_stores_correct_address() {
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvk = ExtendedFullViewingKey::from(&extsk);
+        let ufvk = UnifiedFullViewingKey::from_sapling_extended_full_viewing_key(extfvk.clone());
+
+        let encoded = zcash_client_backend::encoding::encode_unified_full_viewing_key(
+            &tests::network(),
+            &ufvk,
+        );
+        let decoded = decode_unified_full_viewing_key(&tests::network(), &encoded)
+            .unwrap()
+            .unwrap();
+        assert_eq!(decoded, ufvk);
+
+        init_accounts_table_ufvk(&db_data, &[ufvk]).unwrap();
+
+        let pa = get_address(&db_data, AccountId(0)).unwrap();
+        assert_eq!(pa.unwrap(), extsk.default_address().unwrap().1);
+
+        let stored_ufvk: String = db_data
+            .conn
+            .query_row("SELECT ufvk FROM accounts WHERE account = 0", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(stored_ufvk, encoded);
+    }
+
+    #[test]
+    fn import_viewing_account_appends_after_existing_accounts() {
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        let extfvks = [ExtendedFullViewingKey::from(&ExtendedSpendingKey::master(
+            &[],
+        ))];
+        init_accounts_table(&db_data, &extfvks).unwrap();
+
+        let watch_only = ExtendedFullViewingKey::from(&ExtendedSpendingKey::master(&[1]));
+        let account =
+            import_viewing_account(&db_data, &watch_only, BlockHeight::from(500_000)).unwrap();
+        assert_eq!(account, AccountId(1));
+
+        let pa = get_address(&db_data, account).unwrap();
+        assert_eq!(pa.unwrap(), watch_only.default_address().unwrap().1);
+
+        let birthday: u32 = db_data
+            .conn
+            .query_row(
+                "SELECT birthday_height FROM accounts WHERE account = ?",
+                [account.0],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(birthday, 500_000);
+
+        // Importing again appends a further account rather than overwriting.
+        let second =
+            import_viewing_account(&db_data, &watch_only, BlockHeight::from(600_000)).unwrap();
+        assert_eq!(second, AccountId(2));
+    }
 }
@@ -0,0 +1,306 @@
+//! Functions for recording and selecting transparent UTXOs.
+
+use rusqlite::named_params;
+
+use zcash_primitives::{
+    consensus::{self, BlockHeight},
+    legacy::TransparentAddress,
+    transaction::{
+        components::{Amount, OutPoint, TxOut},
+        TxId,
+    },
+};
+
+use zcash_client_backend::{encoding::encode_transparent_address, wallet::WalletTransparentOutput};
+
+use crate::{error::SqliteClientError, DataConnStmtCache, WalletDb};
+
+/// Records a transparent UTXO received by the wallet, so that it can later be selected
+/// for shielding. Returns the id of the inserted row.
+pub fn put_received_transparent_utxo<'a, P: consensus::Parameters>(
+    stmts: &mut DataConnStmtCache<'a, P>,
+    utxo: &WalletTransparentOutput,
+) -> Result<i64, SqliteClientError> {
+    let address_str = encode_transparent_address(
+        &stmts.wallet_db.params.b58_pubkey_address_prefix(),
+        &stmts.wallet_db.params.b58_script_address_prefix(),
+        &utxo.address,
+    );
+
+    let mut script_bytes = vec![];
+    utxo.script().write(&mut script_bytes)?;
+
+    stmts.wallet_db.conn.execute(
+        "INSERT INTO utxos (address, prevout_txid, prevout_idx, script, value_zat, height)
+            VALUES (:address, :prevout_txid, :prevout_idx, :script, :value_zat, :height)
+            ON CONFLICT (prevout_txid, prevout_idx) DO UPDATE
+            SET address = :address, script = :script, value_zat = :value_zat, height = :height",
+        named_params![
+            ":address": &address_str,
+            ":prevout_txid": &utxo.outpoint.hash().to_vec(),
+            ":prevout_idx": &utxo.outpoint.n(),
+            ":script": &script_bytes,
+            ":value_zat": &i64::from(utxo.value),
+            ":height": &u32::from(utxo.height),
+        ],
+    )?;
+
+    Ok(stmts.wallet_db.conn.last_insert_rowid())
+}
+
+/// Inspects a transparent output of a transaction the wallet just created, and if it pays
+/// an address the wallet has previously received funds at, records it as an (as yet
+/// unconfirmed) UTXO so that transparent change is reflected in the wallet's balance.
+///
+/// This relies on the `utxos` table as the record of addresses the wallet controls;
+/// outputs paying any other address are ignored.
+pub fn put_transparent_change<'a, P: consensus::Parameters>(
+    stmts: &mut DataConnStmtCache<'a, P>,
+    txid: &TxId,
+    index: usize,
+    output: &TxOut,
+) -> Result<(), SqliteClientError> {
+    let address = match output.script_pubkey.address() {
+        Some(address) => address,
+        None => return Ok(()),
+    };
+
+    let address_str = encode_transparent_address(
+        &stmts.wallet_db.params.b58_pubkey_address_prefix(),
+        &stmts.wallet_db.params.b58_script_address_prefix(),
+        &address,
+    );
+
+    let is_owned: bool = stmts.wallet_db.conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM utxos WHERE address = :address)",
+        named_params![":address": &address_str],
+        |row| row.get(0),
+    )?;
+
+    if !is_owned {
+        return Ok(());
+    }
+
+    let mut script_bytes = vec![];
+    output.script_pubkey.write(&mut script_bytes)?;
+
+    stmts.wallet_db.conn.execute(
+        "INSERT INTO utxos (address, prevout_txid, prevout_idx, script, value_zat, height)
+            VALUES (:address, :prevout_txid, :prevout_idx, :script, :value_zat, NULL)
+            ON CONFLICT (prevout_txid, prevout_idx) DO UPDATE
+            SET address = :address, script = :script, value_zat = :value_zat",
+        named_params![
+            ":address": &address_str,
+            ":prevout_txid": &txid.0.to_vec(),
+            ":prevout_idx": &(index as u32),
+            ":script": &script_bytes,
+            ":value_zat": &i64::from(output.value),
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Returns the transparent UTXOs received by the given address that are unspent as of the
+/// specified anchor height.
+pub fn get_spendable_transparent_utxos<P: consensus::Parameters>(
+    wdb: &WalletDb<P>,
+    address: &TransparentAddress,
+    anchor_height: BlockHeight,
+) -> Result<Vec<WalletTransparentOutput>, SqliteClientError> {
+    let address_str = encode_transparent_address(
+        &wdb.params.b58_pubkey_address_prefix(),
+        &wdb.params.b58_script_address_prefix(),
+        address,
+    );
+
+    let mut stmt_utxos = wdb.conn.prepare(
+        "SELECT prevout_txid, prevout_idx, value_zat, height
+            FROM utxos
+            WHERE address = :address
+            AND height <= :anchor_height
+            AND spent_in_tx IS NULL",
+    )?;
+
+    let rows = stmt_utxos.query_and_then_named::<_, SqliteClientError, _>(
+        named_params![
+            ":address": &address_str,
+            ":anchor_height": &u32::from(anchor_height),
+        ],
+        |row| {
+            let txid_bytes: Vec<u8> = row.get(0)?;
+            let mut txid = [0u8; 32];
+            txid.copy_from_slice(&txid_bytes);
+            let n: u32 = row.get(1)?;
+            let value = Amount::from_i64(row.get(2)?).unwrap();
+            let height = BlockHeight::from(row.get::<_, u32>(3)?);
+
+            Ok(WalletTransparentOutput {
+                address: address.clone(),
+                outpoint: OutPoint::new(txid, n),
+                value,
+                height,
+            })
+        },
+    )?;
+
+    rows.collect::<Result<_, _>>()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use zcash_primitives::{
+        consensus::BlockHeight,
+        legacy::TransparentAddress,
+        transaction::{
+            components::{Amount, OutPoint, TxOut},
+            TxId,
+        },
+    };
+
+    use zcash_client_backend::{
+        data_api::wallet::{propose_shielding, zip317},
+        wallet::WalletTransparentOutput,
+    };
+
+    use crate::{
+        tests,
+        wallet::init::init_wallet_db,
+        wallet::transparent::{
+            get_spendable_transparent_utxos, put_received_transparent_utxo,
+            put_transparent_change,
+        },
+        NoteId, WalletDb,
+    };
+
+    #[test]
+    fn put_and_get_spendable_transparent_utxo() {
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        let address = TransparentAddress::PublicKey([7; 20]);
+        let utxo = WalletTransparentOutput {
+            address: address.clone(),
+            outpoint: OutPoint::new([1; 32], 0),
+            value: Amount::from_u64(10000).unwrap(),
+            height: BlockHeight::from(1),
+        };
+
+        let mut db_write = db_data.get_update_ops().unwrap();
+        put_received_transparent_utxo(&mut db_write, &utxo).unwrap();
+
+        let utxos =
+            get_spendable_transparent_utxos(&db_data, &address, BlockHeight::from(10)).unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].outpoint, utxo.outpoint);
+        assert_eq!(utxos[0].value(), utxo.value());
+        assert_eq!(utxos[0].address(), &address);
+        assert_eq!(utxos[0].script(), address.script());
+
+        // Not yet mined at the requested anchor height.
+        let utxos =
+            get_spendable_transparent_utxos(&db_data, &address, BlockHeight::from(0)).unwrap();
+        assert!(utxos.is_empty());
+    }
+
+    #[test]
+    fn put_transparent_change_records_output_to_owned_address() {
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        let owned_address = TransparentAddress::PublicKey([7; 20]);
+        let unowned_address = TransparentAddress::PublicKey([8; 20]);
+
+        let mut db_write = db_data.get_update_ops().unwrap();
+        put_received_transparent_utxo(
+            &mut db_write,
+            &WalletTransparentOutput {
+                address: owned_address.clone(),
+                outpoint: OutPoint::new([1; 32], 0),
+                value: Amount::from_u64(10000).unwrap(),
+                height: BlockHeight::from(1),
+            },
+        )
+        .unwrap();
+
+        let change_txid = TxId([2; 32]);
+        put_transparent_change(
+            &mut db_write,
+            &change_txid,
+            0,
+            &TxOut {
+                value: Amount::from_u64(5000).unwrap(),
+                script_pubkey: owned_address.script(),
+            },
+        )
+        .unwrap();
+        put_transparent_change(
+            &mut db_write,
+            &change_txid,
+            1,
+            &TxOut {
+                value: Amount::from_u64(2500).unwrap(),
+                script_pubkey: unowned_address.script(),
+            },
+        )
+        .unwrap();
+
+        let recorded: i64 = db_data
+            .conn
+            .query_row("SELECT COUNT(*) FROM utxos", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(recorded, 2);
+
+        let change_height: Option<u32> = db_data
+            .conn
+            .query_row(
+                "SELECT height FROM utxos WHERE prevout_txid = ?",
+                [change_txid.0.to_vec()],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(change_height, None);
+
+        // The change output isn't spendable until its height is known.
+        let utxos = get_spendable_transparent_utxos(&db_data, &owned_address, BlockHeight::from(10))
+            .unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].outpoint, OutPoint::new([1; 32], 0));
+    }
+
+    #[test]
+    fn propose_shielding_consumes_all_utxos_less_fee() {
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDb::for_path(data_file.path(), tests::network()).unwrap();
+        init_wallet_db(&db_data).unwrap();
+
+        let address = TransparentAddress::PublicKey([7; 20]);
+
+        let mut db_write = db_data.get_update_ops().unwrap();
+        for i in 0..3 {
+            put_received_transparent_utxo(
+                &mut db_write,
+                &WalletTransparentOutput {
+                    address: address.clone(),
+                    outpoint: OutPoint::new([i; 32], 0),
+                    value: Amount::from_u64(10000).unwrap(),
+                    height: BlockHeight::from(1),
+                },
+            )
+            .unwrap();
+        }
+
+        let proposal =
+            propose_shielding::<_, NoteId, _>(&db_data, &address, BlockHeight::from(10)).unwrap();
+
+        assert_eq!(proposal.selected_utxos.len(), 3);
+        let total_value = Amount::from_u64(30000).unwrap();
+        assert_eq!(proposal.selected_value(), total_value);
+        assert_eq!(proposal.fee, zip317::conventional_fee(3, 1));
+        assert_eq!(proposal.shielded_value, total_value - proposal.fee);
+    }
+}